@@ -0,0 +1,217 @@
+use nalgebra::Vector3;
+
+use crate::{
+    drivetrain::ChassisSpeeds,
+    util::{wrap_angle, AngleUnwrapper},
+};
+
+/// A PID controller on an error signal, stepped with an explicit loop period
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PidController {
+    pub p: f32,
+    pub i: f32,
+    pub d: f32,
+    integral: f32,
+    last_error: Option<f32>,
+}
+
+impl PidController {
+    pub const fn new(p: f32, i: f32, d: f32) -> Self {
+        Self {
+            p,
+            i,
+            d,
+            integral: 0.0,
+            last_error: None,
+        }
+    }
+
+    /// Output for `error` after `dt` seconds since the last step. The derivative term is zero on
+    /// the first step after a reset.
+    pub fn calculate(&mut self, error: f32, dt: f32) -> f32 {
+        self.integral += error * dt;
+
+        let derivative = match self.last_error {
+            Some(last) if dt > 0.0 => (error - last) / dt,
+            _ => 0.0,
+        };
+        self.last_error = Some(error);
+
+        self.p * error + self.i * self.integral + self.d * derivative
+    }
+
+    /// Clears the integral and derivative history, for the start of a new move
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last_error = None;
+    }
+}
+
+/// Feedforward plus feedback for following field poses with a holonomic drivetrain. Each axis of
+/// the pose error gets its own PID, whose output is added to the target's own velocity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HolonomicController {
+    /// Field x error in meters to meters per second
+    pub x_pid: PidController,
+    /// Field y error in meters to meters per second
+    pub y_pid: PidController,
+    /// Heading error in radians to radians per second
+    pub theta_pid: PidController,
+    /// Target and measured headings unwrapped since the last reset, so a target that winds
+    /// through whole turns is followed turn for turn even while the robot lags more than half a
+    /// turn behind
+    headings: Option<(AngleUnwrapper, AngleUnwrapper)>,
+}
+
+impl HolonomicController {
+    pub const fn new(x_pid: PidController, y_pid: PidController, theta_pid: PidController) -> Self {
+        Self {
+            x_pid,
+            y_pid,
+            theta_pid,
+            headings: None,
+        }
+    }
+
+    /// Field relative speeds that follow `target_velocity` (m/s and rad/s) while closing the
+    /// error from `current_pose` to `target_pose`, `dt` seconds after the last step. The heading
+    /// error takes the short way around on the first step after a reset, then follows the
+    /// target's winding.
+    pub fn calculate(
+        &mut self,
+        current_pose: Vector3<f32>,
+        target_pose: Vector3<f32>,
+        target_velocity: Vector3<f32>,
+        dt: f32,
+    ) -> ChassisSpeeds {
+        let error = target_pose.xy() - current_pose.xy();
+        let heading_error = self.heading_error(target_pose.z, current_pose.z);
+
+        ChassisSpeeds::new(
+            target_velocity.x + self.x_pid.calculate(error.x, dt),
+            target_velocity.y + self.y_pid.calculate(error.y, dt),
+            target_velocity.z + self.theta_pid.calculate(heading_error, dt),
+        )
+    }
+
+    pub fn reset(&mut self) {
+        self.x_pid.reset();
+        self.y_pid.reset();
+        self.theta_pid.reset();
+        self.headings = None;
+    }
+
+    fn heading_error(&mut self, target_heading: f32, heading: f32) -> f32 {
+        let (target, current) = self.headings.get_or_insert_with(|| {
+            let mut target = AngleUnwrapper::default();
+            let mut current = AngleUnwrapper::default();
+
+            // Start from whichever equivalent of the measured heading is nearest the target
+            target.update(target_heading);
+            current.update(target_heading - wrap_angle(target_heading - heading));
+
+            (target, current)
+        });
+
+        target.update(target_heading) - current.update(heading)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    fn heading_only(p: f32) -> HolonomicController {
+        HolonomicController::new(
+            PidController::default(),
+            PidController::default(),
+            PidController::new(p, 0.0, 0.0),
+        )
+    }
+
+    fn pose(heading: f32) -> Vector3<f32> {
+        Vector3::new(0.0, 0.0, heading)
+    }
+
+    #[test]
+    fn heading_error_starts_the_short_way() {
+        let mut controller = heading_only(1.0);
+
+        let speeds = controller.calculate(pose(3.0), pose(-3.0), Vector3::zeros(), 0.02);
+        assert!((speeds.omega - (2.0 * PI - 6.0)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn winding_target_is_followed_past_half_a_turn_of_lag() {
+        let mut controller = heading_only(1.0);
+
+        // The target spins counterclockwise while the robot is stuck at zero, ending 5 rad ahead
+        let mut omega = 0.0;
+        for step in 0..=10 {
+            let target = wrap_angle(step as f32 * 0.5);
+            omega = controller
+                .calculate(pose(0.0), pose(target), Vector3::zeros(), 0.02)
+                .omega;
+        }
+
+        assert!((omega - 5.0).abs() < 1e-4, "{omega}");
+
+        // A reset forgets the winding
+        controller.reset();
+        let omega = controller
+            .calculate(pose(0.0), pose(wrap_angle(5.0)), Vector3::zeros(), 0.02)
+            .omega;
+        assert!((omega - wrap_angle(5.0)).abs() < 1e-5, "{omega}");
+    }
+
+    #[test]
+    fn measured_heading_crossing_the_wrap_is_continuous() {
+        let mut controller = heading_only(1.0);
+
+        controller.calculate(pose(2.0 * PI - 0.1), pose(0.0), Vector3::zeros(), 0.02);
+        let speeds = controller.calculate(pose(0.05), pose(0.0), Vector3::zeros(), 0.02);
+
+        assert!((speeds.omega + 0.05).abs() < 1e-5);
+    }
+
+    #[test]
+    fn pose_error_feedback_adds_to_the_feedforward() {
+        let mut controller = HolonomicController::new(
+            PidController::new(2.0, 0.0, 0.0),
+            PidController::new(3.0, 0.0, 0.0),
+            PidController::new(4.0, 0.0, 0.0),
+        );
+
+        let speeds = controller.calculate(
+            Vector3::new(1.0, 2.0, 0.1),
+            Vector3::new(1.5, 1.0, 0.2),
+            Vector3::new(0.25, -0.5, 1.0),
+            0.02,
+        );
+
+        assert!((speeds.vx - (0.25 + 2.0 * 0.5)).abs() < 1e-5);
+        assert!((speeds.vy - (-0.5 + 3.0 * -1.0)).abs() < 1e-5);
+        assert!((speeds.omega - (1.0 + 4.0 * 0.1)).abs() < 1e-5);
+
+        // On target the feedforward passes straight through
+        let target = Vector3::new(1.5, 1.0, 0.2);
+        let speeds = controller.calculate(target, target, Vector3::new(0.25, -0.5, 1.0), 0.02);
+        assert!((speeds.vx - 0.25).abs() < 1e-5);
+        assert!((speeds.vy + 0.5).abs() < 1e-5);
+        assert!((speeds.omega - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn pid_integrates_and_differentiates_over_dt() {
+        let mut pid = PidController::new(0.0, 2.0, 0.5);
+
+        // No derivative on the first step
+        assert!((pid.calculate(1.0, 0.1) - 2.0 * 0.1).abs() < 1e-6);
+        assert!((pid.calculate(3.0, 0.1) - (2.0 * 0.4 + 0.5 * 20.0)).abs() < 1e-4);
+
+        pid.reset();
+        assert!((pid.calculate(1.0, 0.1) - 2.0 * 0.1).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+/// Entry ID 0 is reserved for control records
+const CONTROL_ENTRY: u32 = 0;
+const CONTROL_START: u8 = 0;
+
+/// Record header byte for a 4 byte entry ID, 4 byte payload size and 8 byte timestamp. Always
+/// using the widest fields costs a few bytes per record but keeps the writer simple.
+const RECORD_HEADER: u8 = 0b0111_1111;
+
+/// A writer for WPILib's `.wpilog` DataLog format, readable by AdvantageScope and the WPILib
+/// DataLogTool. Timestamps are microseconds since the log was created.
+pub struct DataLog {
+    writer: BufWriter<File>,
+    next_entry: u32,
+    start: Instant,
+}
+
+impl DataLog {
+    /// Creates (or truncates) the log file and writes the format header
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(b"WPILOG")?;
+        // Format version 1.0, then an empty extra header
+        writer.write_all(&0x0100u16.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            next_entry: 1,
+            start: Instant::now(),
+        })
+    }
+
+    /// Registers an entry and returns its ID for appending. `entry_type` is a WPILib type
+    /// string such as `double` or `double[]`.
+    pub fn start_entry(&mut self, name: &str, entry_type: &str) -> anyhow::Result<u32> {
+        let entry = self.next_entry;
+        self.next_entry += 1;
+
+        let mut payload = vec![CONTROL_START];
+        payload.extend_from_slice(&entry.to_le_bytes());
+        for field in [name, entry_type, ""] {
+            payload.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            payload.extend_from_slice(field.as_bytes());
+        }
+
+        self.write_record(CONTROL_ENTRY, &payload)?;
+
+        Ok(entry)
+    }
+
+    /// Appends to a `double` entry
+    pub fn append_double(&mut self, entry: u32, value: f64) -> anyhow::Result<()> {
+        self.write_record(entry, &value.to_le_bytes())
+    }
+
+    /// Appends to a `double[]` entry
+    pub fn append_double_array(&mut self, entry: u32, values: &[f64]) -> anyhow::Result<()> {
+        let payload = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        self.write_record(entry, &payload)
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    fn write_record(&mut self, entry: u32, payload: &[u8]) -> anyhow::Result<()> {
+        let timestamp = self.start.elapsed().as_micros() as u64;
+
+        self.writer.write_all(&[RECORD_HEADER])?;
+        self.writer.write_all(&entry.to_le_bytes())?;
+        self.writer
+            .write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&timestamp.to_le_bytes())?;
+        self.writer.write_all(payload)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    /// Splits a record off the front of `bytes` into its entry, timestamp and payload
+    fn record(bytes: &[u8]) -> (u32, u64, &[u8], &[u8]) {
+        assert_eq!(bytes[0], RECORD_HEADER);
+
+        let entry = u32::from_le_bytes(bytes[1..5].try_into().unwrap());
+        let size = u32::from_le_bytes(bytes[5..9].try_into().unwrap()) as usize;
+        let timestamp = u64::from_le_bytes(bytes[9..17].try_into().unwrap());
+
+        (entry, timestamp, &bytes[17..17 + size], &bytes[17 + size..])
+    }
+
+    #[test]
+    fn writes_the_wpilog_layout() {
+        let path = env::temp_dir().join(format!("swerve_datalog_{}.wpilog", std::process::id()));
+
+        let mut log = DataLog::create(&path).unwrap();
+        let pose = log.start_entry("/pose", "double[]").unwrap();
+        let speed = log.start_entry("/speed", "double").unwrap();
+        log.append_double_array(pose, &[1.0, -2.5]).unwrap();
+        log.append_double(speed, 0.5).unwrap();
+        log.flush().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[..6], b"WPILOG");
+        assert_eq!(&bytes[6..8], &[0x00, 0x01]);
+        assert_eq!(&bytes[8..12], &[0; 4]);
+
+        // Start records: control entry, start type, new entry ID, then name, type and metadata
+        let (entry, _, payload, rest) = record(&bytes[12..]);
+        assert_eq!(entry, CONTROL_ENTRY);
+        let mut expected = vec![CONTROL_START];
+        expected.extend_from_slice(&pose.to_le_bytes());
+        expected.extend_from_slice(&5u32.to_le_bytes());
+        expected.extend_from_slice(b"/pose");
+        expected.extend_from_slice(&8u32.to_le_bytes());
+        expected.extend_from_slice(b"double[]");
+        expected.extend_from_slice(&0u32.to_le_bytes());
+        assert_eq!(payload, expected);
+
+        let (entry, _, payload, rest) = record(rest);
+        assert_eq!(entry, CONTROL_ENTRY);
+        assert_eq!(&payload[1..5], &speed.to_le_bytes());
+        assert_ne!(pose, speed);
+
+        let (entry, first_time, payload, rest) = record(rest);
+        assert_eq!(entry, pose);
+        let values = [1.0f64, -2.5]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(payload, values);
+
+        let (entry, second_time, payload, rest) = record(rest);
+        assert_eq!(entry, speed);
+        assert_eq!(payload, 0.5f64.to_le_bytes());
+        assert!(second_time >= first_time);
+        assert!(rest.is_empty());
+    }
+}
@@ -1,21 +1,51 @@
-use std::f32::consts::PI;
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    f32::consts::{FRAC_PI_2, FRAC_PI_4, PI, TAU},
+    fmt::{self, Write},
+    future::Future,
+    ops::{Deref, DerefMut},
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
+use anyhow::{bail, ensure};
 use math::{
-    kinematics::{module_positions_from_dimensions, Kinematics, SwerveKinematics},
+    kinematics::{module_positions_from_dimensions, Kinematics, SwerveKinematics, SwerveState},
     normalize_angle,
     odometry::Odometry,
 };
 use nalgebra::{Rotation2, Vector2, Vector3};
 use navx::NavX;
-use robotrs::{
-    control::ControlSafe,
-    math::filter::{Filter, SlewRateLimiter},
-    scheduler::spawn,
-    yield_now, FailableDefault,
+use robotrs::{control::ControlSafe, scheduler::spawn, yield_now, FailableDefault};
+use utils::{
+    error::log,
+    subsystem::Subsystem,
+    tracing::{error, info, warn},
+};
+
+use crate::{
+    controller::{HolonomicController, PidController},
+    datalog::DataLog,
+    gyro::Gyro,
+    module_io::SparkMaxModule,
+    nt::NetworkTable,
+    offsets::{load_offsets, save_offsets, DEFAULT_OFFSETS, OFFSETS_PATH},
+    profile::{ProfileState, TrapezoidProfile},
+    replay::{InputSample, InputTrace},
+    saved_pose::{load_pose, save_pose},
+    self_test::{ModuleTestResult, SelfTestReport},
+    slew::SlewLimiter,
+    swerve_module::{DriveControlMode, ModuleGains, SwerveModule, DRIVE_FREE_SPEED},
+    telemetry::DrivetrainSnapshot,
+    trajectory::{Trajectory, TrajectoryEvent},
+    units::{Degrees, Radians},
+    util::{delay, radial_deadband, wrap_angle, AngleUnwrapper, OnDrop},
 };
-use utils::error::log;
 
-use crate::swerve_module::SwerveModule;
+/// Module names in module order, for logs and reports
+pub const MODULE_NAMES: [&str; 4] = ["front left", "front right", "rear left", "rear right"];
 
 /// Meters per second
 const MAX_VELOCITY_LIMIT: f32 = 1.0;
@@ -24,123 +54,4919 @@ const MAX_ROTATION_LIMIT: f32 = 1.0;
 
 const MAX_ACCEL: f32 = 5.0;
 const MAX_ANGLE_ACCEL: f32 = 5.0;
+/// Limit on the rate of change of translation acceleration, disabled when `None`
+const MAX_JERK: Option<f32> = None;
+
+/// Radians per second of turn rate per radian of heading error
+const HEADING_P: f32 = 3.0;
+/// Fraction of `MAX_ROTATION_LIMIT` the heading controller may command
+const HEADING_OUTPUT_FRACTION: f32 = 1.0;
+
+/// Meters per second of correction per meter of position error while following a trajectory
+const TRANSLATION_P: f32 = 2.0;
+
+/// Radians of heading error accepted when driving to a pose
+const POSE_HEADING_TOLERANCE: f32 = 0.05;
+
+/// Radians per second that `turn_to_heading` ramps its setpoint up to
+const HEADING_PROFILE_MAX_VELOCITY: f32 = MAX_ROTATION_LIMIT;
+/// Radians per second squared
+const HEADING_PROFILE_MAX_ACCEL: f32 = MAX_ANGLE_ACCEL;
+/// `turn_to_heading` gives up after this long, enough for a half turn at the profile's limits
+const TURN_TO_HEADING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How close to a cardinal direction the robot must be for wall alignment to snap to it
+const CARDINAL_SNAP_TOLERANCE: f32 = FRAC_PI_4;
+
+/// Fraction a wheel may spin faster than the chassis motion implies before it is limited
+const MAX_SLIP_RATIO: f32 = 0.2;
+/// Meters per second always allowed by traction control, so the robot can start from rest
+const TRACTION_MIN_SPEED: f32 = 0.25;
+
+/// Radians every module must be within before a pre-aligned move starts driving
+const PRE_ALIGN_TOLERANCE: f32 = 0.1;
+/// Pre-alignment gives up waiting and starts driving after this long
+const PRE_ALIGN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Poses kept in the history buffer, one per drive command. At the 20ms loop this is the last
+/// 10 seconds.
+const POSE_HISTORY_LEN: usize = 500;
+
+/// Angles the self test steps each module through
+const SELF_TEST_STEPS: usize = 8;
+const SELF_TEST_STEP_TIME: Duration = Duration::from_millis(500);
+/// Radians
+const SELF_TEST_ANGLE_TOLERANCE: f32 = 0.15;
+/// Meters per second
+const SELF_TEST_DRIVE_SPEED: f32 = 0.3;
+const SELF_TEST_DRIVE_TIME: Duration = Duration::from_millis(500);
+/// Fraction of the expected drive distance that must be measured
+const SELF_TEST_MIN_DISTANCE_RATIO: f32 = 0.5;
+
+/// Radians the robot may be turned off its heading in `brake_hold_heading` before it leaves the
+/// X-stance to turn back
+const BRAKE_HOLD_HEADING_TOLERANCE: f32 = 0.05;
+
+/// Volts per second the turn characterization ramps up at
+const TURN_CHARACTERIZATION_RAMP_RATE: f32 = 0.5;
+const TURN_CHARACTERIZATION_MAX_VOLTAGE: f32 = 6.0;
+/// Radians per second of average steering speed that ends the ramp early
+const TURN_CHARACTERIZATION_MAX_SPEED: f32 = 4.0 * PI;
+/// Radians per second below which a module counts as not yet moving
+const TURN_CHARACTERIZATION_MIN_SPEED: f32 = 0.1;
+
+/// Whether `check_turn_wrapping` steers the modules to check each turn motor crosses the encoder
+/// seam the short way
+const VERIFY_TURN_WRAPPING: bool = true;
+/// Radians either side of the encoder seam the wrapping check steers between
+const WRAP_CHECK_ANGLE: f32 = 0.3;
+const WRAP_CHECK_STEP_TIME: Duration = Duration::from_millis(500);
+
+/// Volts the drive feedforward is scaled against
+const NOMINAL_VOLTAGE: f32 = 12.0;
+/// Volts per meter per second
+const DRIVE_KV: f32 = NOMINAL_VOLTAGE / DRIVE_FREE_SPEED;
+/// Volts per meter per second squared, used both by `scale_to_voltage` and as the acceleration
+/// feedforward on module velocity setpoints
+const DRIVE_KA: f32 = 0.0;
+
+/// Radians per second of corrective turn rate per radian per second of unwanted rotation
+const ROTATION_COMPENSATION_GAIN: f32 = 0.5;
+/// Turn commands smaller than this (rad/s) count as pure translation for rotation compensation
+const ROTATION_COMPENSATION_THRESHOLD: f32 = 0.01;
+
+/// Radius of the circular deadband on the normalized translation input
+const TRANSLATION_DEADBAND: f32 = 0.1;
+
+/// Commands slower than this (m/s and rad/s) hold the modules in place instead of steering
+const SPEED_DEADBAND: f32 = 0.01;
+
+/// Loop period assumed before the first measurement or after a gap in commands
+const NOMINAL_LOOP_PERIOD: Duration = Duration::from_millis(20);
+/// Measured periods longer than this are treated as a gap (e.g. re-enable) rather than a slow loop
+const MAX_LOOP_PERIOD: Duration = Duration::from_millis(100);
+
+/// Meters per second cap while nudging, slow enough to stop precisely
+const NUDGE_MAX_SPEED: f32 = 0.15;
+/// Meters from the target a nudge must get within
+const NUDGE_TOLERANCE: f32 = 0.01;
+/// Nudges that haven't settled by now give up
+const NUDGE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Radians of tilt on both axes within which `balance` counts the robot as level and brakes
+const BALANCE_TOLERANCE: f32 = 2.5 * PI / 180.0;
+
+/// How long the drivetrain must stay within tolerance for `wait_until_settled`
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// Meters from the start pose `return_to_start` settles within
+const RETURN_TO_START_TOLERANCE: f32 = 0.05;
+
+/// Minimum time between odometry updates, 200Hz
+const ODOMETRY_PERIOD: Duration = Duration::from_millis(5);
+/// Odometry iterations taking longer than this many periods are logged as the CPU falling behind
+const ODOMETRY_SLOW_FACTOR: f32 = 2.0;
+/// Minimum time between slow odometry warnings
+const ODOMETRY_SLOW_WARNING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Minimum time between pose publishes from the odometry task, the nominal loop rate
+const POSE_PUBLISH_PERIOD: Duration = NOMINAL_LOOP_PERIOD;
+
+/// Odometry that hasn't updated for longer than this is logged and not trusted for pose moves
+const ODOMETRY_STALE_THRESHOLD: Duration = Duration::from_millis(100);
+
+/// Radians the odometry heading may differ from the gyro before it is logged
+const HEADING_DRIFT_THRESHOLD: f32 = 0.05;
+
+/// G of horizontal acceleration treated as a collision when collision abort is enabled
+const COLLISION_THRESHOLD: f32 = 1.5;
+
+/// Fraction of the speed limits allowed at the moment of enable when soft start is on
+const SOFT_START_INITIAL: f32 = 0.25;
+
+/// Fraction of the translation command kept once stall protection kicks in
+const STALL_OUTPUT_SCALE: f32 = 0.3;
+
+/// Fraction of the acceleration kept on an axis the robot is tipping along
+const ANTI_TIP_ACCEL_SCALE: f32 = 0.25;
+
+/// Time between cutting each module in `stop_staggered`
+const STOP_STAGGER: Duration = Duration::from_millis(5);
+
+/// Closed loop gains in module order
+const MODULE_GAINS: [ModuleGains; 4] = [ModuleGains::DEFAULT; 4];
+
+/// Meters per second of measured chassis speed that counts as moving, above encoder noise. A
+/// sensible default for `is_moving`.
+pub const MOVING_LINEAR_THRESHOLD: f32 = 0.05;
+/// Radians per second of measured gyro rate that counts as moving
+pub const MOVING_ANGULAR_THRESHOLD: f32 = 0.05;
+
+/// Meters, wall to wall along the alliance axis
+pub const FIELD_LENGTH: f32 = 16.541;
+/// Meters
+pub const FIELD_WIDTH: f32 = 8.211;
 
 const TRACK_WIDTH: f32 = 0.7239;
 const WHEEL_BASE: f32 = 0.6096;
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alliance {
+    #[default]
+    Blue,
+    Red,
+}
+
+impl Alliance {
+    /// Radians between the robot's zero heading and this alliance's field forward
+    pub fn heading_offset(&self) -> f32 {
+        match self {
+            Alliance::Blue => 0.0,
+            Alliance::Red => PI,
+        }
+    }
+
+    /// Converts a `PoseConvention::Native` pose on this alliance to `PoseConvention::Wpilib`
+    pub fn to_wpilib(&self, pose: Vector3<f32>) -> Vector3<f32> {
+        match self {
+            Alliance::Blue => Vector3::new(pose.x, pose.y, wrap_angle(pose.z)),
+            Alliance::Red => Vector3::new(
+                FIELD_LENGTH - pose.x,
+                FIELD_WIDTH - pose.y,
+                wrap_angle(pose.z + PI),
+            ),
+        }
+    }
+
+    /// Converts a `PoseConvention::Wpilib` pose to `PoseConvention::Native` on this alliance.
+    /// The inverse of `to_wpilib`.
+    pub fn from_wpilib(&self, pose: Vector3<f32>) -> Vector3<f32> {
+        match self {
+            Alliance::Blue => Vector3::new(pose.x, pose.y, normalize_angle(pose.z)),
+            Alliance::Red => Vector3::new(
+                FIELD_LENGTH - pose.x,
+                FIELD_WIDTH - pose.y,
+                normalize_angle(pose.z + PI),
+            ),
+        }
+    }
+}
+
+/// Frames a pose (x, y, heading) can be expressed in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PoseConvention {
+    /// What `get_pose` returns. Meters from wherever `reset_pose` put the origin, with x along
+    /// the field forward of the alliance set by `set_field_origin` and y to its left. Heading is
+    /// in radians, counterclockwise positive from field forward, in `[0, 2 * PI)`.
+    #[default]
+    Native,
+    /// WPILib's field coordinates, as used by dashboards and path tools. Meters from the right
+    /// corner of the blue alliance wall as seen by the blue drivers, x toward the red wall and y
+    /// to the left. Heading is in radians, counterclockwise positive from +x, in `[-PI, PI)`.
+    /// Converting to it assumes `reset_pose` was given alliance relative coordinates, with the
+    /// origin at the right corner of our own alliance wall.
+    Wpilib,
+}
+
+/// A normalized drive command in the same units as `Drivetrain::set_input`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DriveCommand {
+    pub drive: Vector2<f32>,
+    pub turn_rate: f32,
+}
+
+/// Chassis velocity in meters per second and radians per second
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChassisSpeeds {
+    pub vx: f32,
+    pub vy: f32,
+    pub omega: f32,
+}
+
+impl ChassisSpeeds {
+    pub fn new(vx: f32, vy: f32, omega: f32) -> Self {
+        Self { vx, vy, omega }
+    }
+
+    /// Robot relative speeds for driving forward along a circular arc. A positive radius curves
+    /// counterclockwise (left), a negative one clockwise, and an infinite one drives straight.
+    pub fn from_arc(radius: f32, speed: f32) -> Self {
+        Self::new(speed, 0.0, speed / radius)
+    }
+
+    pub fn velocity(&self) -> Vector2<f32> {
+        Vector2::new(self.vx, self.vy)
+    }
+
+    /// Speeds that, held in a straight line for `dt` seconds, end at the same pose as following
+    /// these speeds along their arc. Commanding translation and rotation together otherwise
+    /// skews the path toward the direction of rotation, since each command is held for a whole
+    /// loop while the heading changes.
+    pub fn discretize(&self, dt: f32) -> Self {
+        if dt <= 0.0 || self.omega == 0.0 {
+            return *self;
+        }
+
+        // Log map of the pose reached by moving (vx, vy) * dt while turning omega * dt
+        let angle = self.omega * dt;
+        let half_angle = angle / 2.0;
+        let cos_minus_one = angle.cos() - 1.0;
+
+        let half_angle_by_tan = if cos_minus_one.abs() < 1e-6 {
+            1.0 - angle * angle / 12.0
+        } else {
+            -(half_angle * angle.sin()) / cos_minus_one
+        };
+
+        let translation = Rotation2::new((-half_angle).atan2(half_angle_by_tan))
+            * self.velocity()
+            * dt
+            * half_angle_by_tan.hypot(half_angle);
+
+        Self::new(translation.x / dt, translation.y / dt, self.omega)
+    }
+
+    /// `(vx, vy, omega)`, the layout the kinematics take
+    pub fn as_vector(&self) -> Vector3<f32> {
+        Vector3::new(self.vx, self.vy, self.omega)
+    }
+}
+
+/// Returned by trajectory following and `drive_to_pose` when they abort on a collision. Check for
+/// it with `err.downcast_ref::<CollisionDetected>()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CollisionDetected {
+    /// G of horizontal acceleration that tripped the abort
+    pub acceleration: f32,
+}
+
+impl fmt::Display for CollisionDetected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Collision detected ({:.2}g), move aborted",
+            self.acceleration
+        )
+    }
+}
+
+impl std::error::Error for CollisionDetected {}
+
+/// Gains for `balance`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BalanceGains {
+    /// Meters per second toward the raised side per radian of tilt
+    pub p: f32,
+    /// Meters per second
+    pub max_speed: f32,
+}
+
+impl Default for BalanceGains {
+    fn default() -> Self {
+        Self {
+            p: 0.6,
+            max_speed: 0.4,
+        }
+    }
+}
+
+/// Steering feedforward fitted by `characterize_turn`, volts = ks + kv * radians per second
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TurnCharacterization {
+    /// Volts to overcome static friction
+    pub ks: f32,
+    /// Volts per radian per second
+    pub kv: f32,
+}
+
+/// The frame a driver input is interpreted in, see `set_input_with_frames`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputFrame {
+    #[default]
+    Field,
+    Robot,
+}
+
+/// How a command that would drive some wheel past the free speed is brought back within reach
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SaturationPolicy {
+    /// Scale translation and rotation down together, keeping the direction of motion and the
+    /// curvature of the path
+    #[default]
+    Proportional,
+    /// Keep the turn rate and give up translation first, e.g. to keep aiming while moving
+    PreserveRotation,
+    /// Keep the translation and give up turn rate first
+    PreserveTranslation,
+}
+
+/// What the drivetrain does while fewer than four modules are healthy. Modules that can't be read
+/// are always stopped rather than commanded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DegradedPolicy {
+    /// Keep driving the healthy modules at full speed
+    Ignore,
+    /// Keep driving the healthy modules with translation and rotation scaled by this fraction
+    LimitSpeed(f32),
+    /// Stop every module until all four are healthy again
+    Stop,
+}
+
+impl Default for DegradedPolicy {
+    fn default() -> Self {
+        DegradedPolicy::LimitSpeed(0.3)
+    }
+}
+
+/// When the drivetrain counts as pushing against something it can't move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StallThresholds {
+    /// Amps, averaged over the drive motors
+    pub current: f32,
+    /// Meters per second, averaged over the modules. Slower than this counts as not moving.
+    pub velocity: f32,
+    /// Meters per second of commanded translation needed before the robot can be stalled
+    pub command: f32,
+}
+
+impl Default for StallThresholds {
+    fn default() -> Self {
+        Self {
+            current: 40.0,
+            velocity: 0.1,
+            command: 0.2,
+        }
+    }
+}
+
+/// Weights for combining a driver command with an assist command, per channel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlendWeights {
+    pub driver_translation: f32,
+    pub assist_translation: f32,
+    pub driver_rotation: f32,
+    pub assist_rotation: f32,
+}
+
+impl DriveCommand {
+    /// Weighted sum of two commands, clamped back into the normalized input range
+    pub fn blend(self, assist: DriveCommand, weights: BlendWeights) -> DriveCommand {
+        let drive =
+            self.drive * weights.driver_translation + assist.drive * weights.assist_translation;
+        let turn_rate =
+            self.turn_rate * weights.driver_rotation + assist.turn_rate * weights.assist_rotation;
+
+        DriveCommand {
+            drive: drive.cap_magnitude(1.0),
+            turn_rate: turn_rate.clamp(-1.0, 1.0),
+        }
+    }
+}
+
+/// Running sum of squared heading errors, so the RMS is O(1) to update and query
+#[derive(Clone, Copy, Debug, Default)]
+struct HeadingErrorStats {
+    sum_squares: f32,
+    samples: u32,
+}
+
+impl HeadingErrorStats {
+    fn record(&mut self, error: f32) {
+        self.sum_squares += error * error;
+        self.samples += 1;
+    }
+
+    fn rms(&self) -> Option<f32> {
+        if self.samples == 0 {
+            None
+        } else {
+            Some((self.sum_squares / self.samples as f32).sqrt())
+        }
+    }
+}
+
 pub struct Drivetrain {
     modules: [SwerveModule; 4],
 
     kinematics: SwerveKinematics,
+    /// Meters from the robot center, x forward and y left
+    module_positions: [Vector2<f32>; 4],
     odometry: Odometry<SwerveKinematics>,
-    gyro: NavX,
+    /// When the odometry task last updated successfully
+    last_odometry_update: Rc<Cell<Instant>>,
+    /// Shared with the odometry task, `None` to update as fast as the scheduler allows
+    odometry_period: Rc<Cell<Option<Duration>>>,
+    /// Hz, measured over the odometry task's last iteration
+    odometry_rate: Rc<Cell<f32>>,
+    warned_odometry_stale: bool,
+    warned_heading_drift: bool,
+    gyro: Rc<dyn Gyro>,
+    /// Sum of `gyro_zero` and the field origin offset, shared with the odometry task
+    heading_offset: Rc<Cell<f32>>,
+    gyro_zero: f32,
+    field_origin: Alliance,
+    warned_gyro_not_ready: bool,
+
+    heading_setpoint: Option<f32>,
+    heading_error: HeadingErrorStats,
+    /// The pose passed to the last `reset_pose`
+    start_pose: Vector3<f32>,
+    /// The target of the last `drive_to_pose` since the pose was reset
+    pose_target: Option<Vector3<f32>>,
+    heading_profile: TrapezoidProfile,
+    heading_output_fraction: f32,
+    /// Pose feedback for `drive_to_pose` and trajectory following
+    holonomic_controller: HolonomicController,
+    /// When the trajectory being followed ends, shared with a guard that clears it if following
+    /// is dropped early
+    trajectory_end: Rc<Cell<Option<Instant>>>,
+    /// Radians of pitch or roll above which acceleration in that direction is cut back
+    anti_tip_threshold: Option<f32>,
+
+    speed_deadband: f32,
+    /// Volts per meter per second squared
+    drive_ka: f32,
+    translation_deadband: f32,
+    traction_control: bool,
+    rotation_compensation: bool,
+    rotation_compensation_gain: f32,
+    cardinal_snap_tolerance: f32,
+    /// Counterclockwise arc `(min, max)` in radians the heading is kept within
+    heading_limits: Option<(f32, f32)>,
+    pre_align: bool,
+    commanded_speeds: ChassisSpeeds,
+
+    /// Shared with the odometry task, which publishes every loop
+    pose_publisher: Rc<RefCell<Option<(Rc<dyn NetworkTable>, String)>>>,
+    /// Set while the NetworkTables command task drives, cleared by driver input
+    nt_command_mode: bool,
+    pose_history: VecDeque<(Instant, Vector3<f32>)>,
+    /// When the recording started and every `set_input` since
+    input_recording: Option<(Instant, Vec<InputSample>)>,
+    datalog: Option<DrivetrainLog>,
+
+    x_limit: SlewLimiter,
+    y_limit: SlewLimiter,
+    angle_limit: SlewLimiter,
+    instant_stop_on_release: bool,
+
+    loop_period_override: Option<Duration>,
+    measured_loop_period: Duration,
+    last_loop: Option<Instant>,
 
-    x_limit: SlewRateLimiter,
-    y_limit: SlewRateLimiter,
-    angle_limit: SlewRateLimiter,
+    auto_brake_delay: Option<Duration>,
+    released_since: Option<Instant>,
+
+    stop_stagger: Duration,
+
+    enabled: bool,
+    emergency_stopped: bool,
+    coast_on_disable: bool,
+    /// Whether the turn wrapping check still has to run
+    verify_turn_wrapping: bool,
+    soft_start: Option<Duration>,
+    enabled_at: Option<Instant>,
+
+    balance_gains: BalanceGains,
+    balance_tolerance: f32,
+
+    collision_abort: bool,
+    /// G
+    collision_threshold: f32,
+
+    saturation_policy: SaturationPolicy,
+
+    /// Whether each module could be read on the last command, in module order
+    module_healthy: [bool; 4],
+    degraded_policy: DegradedPolicy,
+
+    stall_thresholds: StallThresholds,
+    stall_protection: Option<Duration>,
+    stalled_since: Option<Instant>,
 }
 
 impl Drivetrain {
+    /// The odometry pose in `PoseConvention::Native`
     pub fn get_pose(&self) -> Vector3<f32> {
-        self.odometry.get_pose()
+        let pose = self.odometry.get_pose();
+
+        Vector3::new(pose.x, pose.y, normalize_angle(pose.z))
     }
 
-    pub fn set_input_raw(&mut self, drive: Vector2<f32>, turn_rate: f32) -> anyhow::Result<()> {
-        let drive = Rotation2::new(-self.get_heading()).matrix() * drive;
+    /// The odometry pose in the given convention
+    pub fn get_pose_in(&self, convention: PoseConvention) -> Vector3<f32> {
+        let pose = self.get_pose();
 
-        for (module, state) in self.modules.iter_mut().zip(
-            self.kinematics
-                .inverse(drive.fixed_resize(turn_rate))
-                .into_iter(),
-        ) {
-            module.set_target(state)?;
+        match convention {
+            PoseConvention::Native => pose,
+            PoseConvention::Wpilib => self.field_origin.to_wpilib(pose),
+        }
+    }
+
+    /// Planar distance in meters from the current pose to a field point
+    pub fn distance_to(&self, target: Vector2<f32>) -> f32 {
+        (target - self.get_pose().xy()).norm()
+    }
+
+    /// Shortest signed rotation in radians from the current heading to `target_heading`,
+    /// counterclockwise positive and wrapped into `[-PI, PI)`
+    pub fn heading_error_to(&self, target_heading: f32) -> f32 {
+        wrap_angle(target_heading - self.get_heading())
+    }
+
+    /// Resets odometry to the given pose and rezeroes the gyro so that `get_heading` matches it
+    pub fn reset_pose(&mut self, pose: Vector3<f32>) {
+        self.gyro_zero = pose.z - gyro_yaw(&*self.gyro).0 - self.field_origin.heading_offset();
+        self.update_heading_offset();
+        self.odometry.set_pose(pose);
+        self.start_pose = pose;
+        self.pose_target = None;
+        self.reset_heading_error();
+    }
+
+    /// Sets which alliance's wall field forward points away from, rotating `get_heading` and the
+    /// odometry heading by 180 degrees for red. Set this once at init from the driver station;
+    /// it composes with the zeroing done by `reset_pose`.
+    pub fn set_field_origin(&mut self, alliance: Alliance) {
+        self.field_origin = alliance;
+        self.update_heading_offset();
+        self.realign_heading();
+    }
+
+    fn update_heading_offset(&self) {
+        self.heading_offset
+            .set(self.gyro_zero + self.field_origin.heading_offset());
+    }
+
+    /// Integrates one odometry step from measured module states, the same step the background
+    /// odometry task takes each loop, so simulation and replay can drive odometry without it.
+    /// Each state's drive value is the distance in meters the wheel moved since the last update,
+    /// with its angle relative to the robot. `heading` is the field heading in radians and must
+    /// be continuous across updates rather than wrapped.
+    pub fn update_odometry(&self, states: [SwerveState; 4], heading: f32) {
+        integrate_odometry(&self.odometry, &self.last_odometry_update, states, heading);
+    }
+
+    /// Time since the odometry task last updated the pose. Read errors in the task are logged and
+    /// skipped, so a module that keeps failing shows up here as a growing staleness.
+    pub fn odometry_staleness(&self) -> Duration {
+        self.last_odometry_update.get().elapsed()
+    }
+
+    /// Sets the minimum time between odometry updates, or `None` to update as fast as the
+    /// scheduler allows. Defaults to 200Hz, which keeps integration steps even and stops the
+    /// task outrunning the encoders. The wait is a `delay`, which only rate limits the updates:
+    /// the task is still polled every scheduler pass, so it doesn't hand the CPU back.
+    pub fn set_odometry_period(&mut self, period: Option<Duration>) {
+        self.odometry_period.set(period);
+    }
+
+    /// Hz the odometry task is updating at, measured over its last iteration
+    pub fn odometry_rate(&self) -> f32 {
+        self.odometry_rate.get()
+    }
+
+    fn check_odometry_staleness(&mut self) {
+        let staleness = self.odometry_staleness();
+
+        if staleness <= ODOMETRY_STALE_THRESHOLD {
+            self.warned_odometry_stale = false;
+        } else if !self.warned_odometry_stale {
+            warn!("Odometry has not updated for {staleness:?}");
+            self.warned_odometry_stale = true;
+        }
+    }
+
+    /// Wrapped difference in radians between the gyro heading and the odometry heading,
+    /// counterclockwise positive. Odometry is fed the gyro heading directly, so anything beyond
+    /// the lag of one update means the gyro or the odometry is misbehaving.
+    pub fn heading_drift(&self) -> f32 {
+        wrap_angle(self.get_heading() - self.get_pose().z)
+    }
+
+    fn check_heading_drift(&mut self) {
+        let drift = self.heading_drift();
+
+        if drift.abs() <= HEADING_DRIFT_THRESHOLD {
+            self.warned_heading_drift = false;
+        } else if !self.warned_heading_drift {
+            warn!("Odometry heading has drifted {drift:.3} rad from the gyro");
+            self.warned_heading_drift = true;
+        }
+    }
+
+    /// Stops and errors if the pose is too stale to drive on
+    fn ensure_odometry_fresh(&mut self) -> anyhow::Result<()> {
+        let staleness = self.odometry_staleness();
+
+        if staleness > ODOMETRY_STALE_THRESHOLD {
+            self.heading_setpoint = None;
+            self.stop();
+
+            bail!("Odometry has not updated for {staleness:?}, not trusting the pose");
         }
 
         Ok(())
     }
 
-    pub fn get_heading(&self) -> f32 {
-        normalize_angle(-self.gyro.heading().to_radians())
+    /// Rotates a field relative vector into the robot frame (x forward, y left) at the current
+    /// heading
+    pub fn field_to_robot(&self, vector: Vector2<f32>) -> Vector2<f32> {
+        Rotation2::new(-self.get_heading()) * vector
     }
 
-    pub fn set_input(&mut self, drive: Vector2<f32>, turn_rate: f32) -> anyhow::Result<()> {
-        let drive = Vector2::new(self.x_limit.apply(drive.x)?, self.y_limit.apply(drive.y)?)
-            .scale(MAX_VELOCITY_LIMIT);
-        let turn_rate = self.angle_limit.apply(turn_rate)? * MAX_ROTATION_LIMIT;
+    /// Rotates a robot relative vector (x forward, y left) into the field frame at the current
+    /// heading. The inverse of `field_to_robot`.
+    pub fn robot_to_field(&self, vector: Vector2<f32>) -> Vector2<f32> {
+        Rotation2::new(self.get_heading()) * vector
+    }
 
-        self.set_input_raw(drive, turn_rate)
+    /// Forces the odometry heading to match the gyro without touching the x/y estimate
+    pub fn realign_heading(&self) {
+        let pose = self.get_pose();
+        self.odometry
+            .set_pose(Vector3::new(pose.x, pose.y, self.get_heading()));
     }
 
-    pub fn brake(&mut self) -> anyhow::Result<()> {
-        for (module, state) in self
+    pub fn set_input_raw(&mut self, drive: Vector2<f32>, turn_rate: f32) -> anyhow::Result<()> {
+        self.command(drive, turn_rate, true, None)
+    }
+
+    /// Commands a translation in meters per second and turn rate in radians per second, with
+    /// `drive` field relative when `field_relative` is set and robot relative otherwise. The
+    /// command is discretized over `period`, or the loop period if `None`.
+    fn command(
+        &mut self,
+        drive: Vector2<f32>,
+        turn_rate: f32,
+        field_relative: bool,
+        period: Option<Duration>,
+    ) -> anyhow::Result<()> {
+        if !self.enabled || self.emergency_stopped {
+            return Ok(());
+        }
+
+        self.ensure_velocity_control()?;
+
+        if let Some(setpoint) = self.heading_setpoint {
+            self.heading_error.record(self.heading_error_to(setpoint));
+        }
+
+        self.record_pose();
+        self.write_datalog();
+        self.check_odometry_staleness();
+        self.check_heading_drift();
+        self.check_module_health();
+
+        let scale = match self.degraded_policy {
+            _ if self.healthy_module_count() == 4 => 1.0,
+            DegradedPolicy::Ignore => 1.0,
+            DegradedPolicy::LimitSpeed(scale) => scale,
+            DegradedPolicy::Stop => 0.0,
+        };
+        let (drive, turn_rate) = (drive * scale, turn_rate * scale);
+
+        if drive.norm() < self.speed_deadband && turn_rate.abs() < self.speed_deadband {
+            self.commanded_speeds = ChassisSpeeds::default();
+
+            for (module, healthy) in self.modules.iter_mut().zip(self.module_healthy) {
+                if healthy {
+                    module.hold()?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        let turn_rate = self.compensate_rotation(drive, turn_rate);
+        let turn_rate = self.limit_turn_rate(turn_rate);
+
+        let drive = if !field_relative {
+            drive
+        } else if self.gyro_ready() {
+            self.warned_gyro_not_ready = false;
+            self.field_to_robot(drive)
+        } else {
+            if !self.warned_gyro_not_ready {
+                warn!("Gyro is not calibrated, driving robot relative");
+                self.warned_gyro_not_ready = true;
+            }
+            drive
+        };
+
+        let drive = if self.stall_protected() {
+            drive * STALL_OUTPUT_SCALE
+        } else {
+            drive
+        };
+
+        let drive = self.limit_tip(drive);
+
+        let period = period.unwrap_or(self.loop_period()).as_secs_f32();
+        let speeds = ChassisSpeeds::new(drive.x, drive.y, turn_rate).discretize(period);
+        let speeds = self.desaturate(speeds);
+        let accel = (speeds.as_vector() - self.commanded_speeds.as_vector())
+            / self.loop_period().as_secs_f32();
+        self.commanded_speeds = speeds;
+
+        let mut states = self.kinematics.inverse(speeds.as_vector());
+        let feedforward = self.accel_feedforward(&states, accel);
+        for (state, feedforward) in states.iter_mut().zip(feedforward) {
+            state.drive += feedforward;
+        }
+
+        if self.traction_control {
+            if let Some(limits) = self.traction_limits() {
+                for (state, limit) in states.iter_mut().zip(limits) {
+                    state.drive = state.drive.clamp(-limit, limit);
+                }
+            }
+        }
+
+        for ((module, state), healthy) in self
             .modules
             .iter_mut()
-            .zip(self.kinematics.brake().into_iter())
+            .zip(states.into_iter())
+            .zip(self.module_healthy)
         {
-            module.set_target(state)?;
+            if healthy {
+                module.set_target(state)?;
+            }
         }
 
         Ok(())
     }
-}
 
-impl FailableDefault for Drivetrain {
-    fn failable_default() -> anyhow::Result<Self> {
-        let kinematics =
-            SwerveKinematics::new(module_positions_from_dimensions(TRACK_WIDTH, WHEEL_BASE));
+    /// When enabled, any axis where the robot is pitched or rolled more than `threshold` radians
+    /// only gets a fraction of the acceleration that would tip it further. Accelerating forward
+    /// or braking from reverse lifts the front, so tilt and acceleration with the same sign on an
+    /// axis mean the command is making the tip worse. Off by default.
+    pub fn set_anti_tip(&mut self, enabled: bool, threshold: f32) {
+        self.anti_tip_threshold = enabled.then_some(threshold);
+    }
 
-        let (front_left, mut front_left_state) =
-            SwerveModule::new(3, 4, Rotation2::new(-PI / 2.0))?;
-        let (front_right, mut front_right_state) = SwerveModule::new(1, 2, Rotation2::new(0.0))?;
-        let (rear_left, mut rear_left_state) = SwerveModule::new(5, 6, Rotation2::new(PI))?;
-        let (rear_right, mut rear_right_state) = SwerveModule::new(7, 8, Rotation2::new(PI / 2.0))?;
+    /// Cuts back the part of a robot relative translation command that accelerates in the
+    /// direction the robot is tipping
+    fn limit_tip(&self, drive: Vector2<f32>) -> Vector2<f32> {
+        let Some(threshold) = self.anti_tip_threshold else {
+            return drive;
+        };
 
-        let odometry = Odometry::new(kinematics.clone(), Vector3::new(0.0, 0.0, 0.0));
-        let odometry2 = odometry.clone();
+        let tilt = Vector2::new(self.get_pitch(), self.get_roll());
+        let previous = self.commanded_speeds.velocity();
 
-        let gyro = NavX::new(hal::spi::RioSPI::new(hal::spi::Port::MXP)?, 60);
-        let gyro2 = gyro.clone();
+        let mut limited = drive;
+        for axis in 0..2 {
+            let accel = drive[axis] - previous[axis];
 
-        spawn(async move {
-            loop {
-                let _ = log(async {
-                    odometry2.update(
-                        [
-                            front_left_state()?,
-                            front_right_state()?,
-                            rear_left_state()?,
-                            rear_right_state()?,
-                        ],
-                        -gyro2.heading().to_radians(),
-                    );
+            if tilt[axis].abs() > threshold && accel.signum() == tilt[axis].signum() {
+                limited[axis] = previous[axis] + accel * ANTI_TIP_ACCEL_SCALE;
+            }
+        }
 
-                    anyhow::Ok(())
-                })
-                .await;
+        limited
+    }
 
-                yield_now().await;
+    pub fn set_saturation_policy(&mut self, policy: SaturationPolicy) {
+        self.saturation_policy = policy;
+    }
+
+    /// Brings robot relative speeds that would drive some wheel past the free speed back within
+    /// reach, according to the saturation policy
+    fn desaturate(&self, speeds: ChassisSpeeds) -> ChassisSpeeds {
+        let speeds = speeds.as_vector();
+        let translation = Vector3::new(speeds.x, speeds.y, 0.0);
+        let rotation = Vector3::new(0.0, 0.0, speeds.z);
+
+        let desaturated = match self.saturation_policy {
+            SaturationPolicy::Proportional => {
+                let peak = self.peak_module_speed(speeds);
+
+                if peak > DRIVE_FREE_SPEED {
+                    speeds * (DRIVE_FREE_SPEED / peak)
+                } else {
+                    speeds
+                }
             }
-        })
-        .detach();
+            SaturationPolicy::PreserveRotation => self.preserve_speeds(rotation, translation),
+            SaturationPolicy::PreserveTranslation => self.preserve_speeds(translation, rotation),
+        };
 
-        Ok(Self {
-            angle_limit: SlewRateLimiter::new(MAX_ANGLE_ACCEL)?,
-            x_limit: SlewRateLimiter::new(MAX_ACCEL)?,
-            y_limit: SlewRateLimiter::new(MAX_ACCEL)?,
+        ChassisSpeeds::new(desaturated.x, desaturated.y, desaturated.z)
+    }
 
-            odometry,
-            kinematics,
-            gyro,
+    /// Keeps as much of `kept` as fits within the free speed, then adds the largest fraction of
+    /// `scaled` that still fits
+    fn preserve_speeds(&self, kept: Vector3<f32>, scaled: Vector3<f32>) -> Vector3<f32> {
+        let kept_peak = self.peak_module_speed(kept);
+        if kept_peak >= DRIVE_FREE_SPEED {
+            return kept * (DRIVE_FREE_SPEED / kept_peak);
+        }
 
-            modules: [front_left, front_right, rear_left, rear_right],
-        })
+        let kept_vectors = self.module_vectors(kept);
+        let scaled_vectors = self.module_vectors(scaled);
+
+        // Largest k in [0, 1] with |kept + k * scaled| <= free speed at every module
+        let fraction = kept_vectors
+            .iter()
+            .zip(&scaled_vectors)
+            .map(|(kept, scaled)| {
+                let a = scaled.norm_squared();
+                if a == 0.0 {
+                    return 1.0;
+                }
+
+                let b = kept.dot(scaled);
+                let c = kept.norm_squared() - DRIVE_FREE_SPEED * DRIVE_FREE_SPEED;
+
+                ((-b + (b * b - a * c).sqrt()) / a).clamp(0.0, 1.0)
+            })
+            .fold(1.0, f32::min);
+
+        kept + scaled * fraction
     }
-}
 
-impl ControlSafe for Drivetrain {
-    fn stop(&mut self) {
-        for module in &mut self.modules {
-            module.stop();
+    /// Fastest wheel speed in meters per second the robot relative speeds would need
+    fn peak_module_speed(&self, speeds: Vector3<f32>) -> f32 {
+        self.module_vectors(speeds)
+            .iter()
+            .map(|vector| vector.norm())
+            .fold(0.0, f32::max)
+    }
+
+    /// Robot relative wheel velocity vectors for robot relative speeds, in module order
+    fn module_vectors(&self, speeds: Vector3<f32>) -> [Vector2<f32>; 4] {
+        self.kinematics
+            .inverse(speeds)
+            .map(|state| Vector2::new(state.angle.cos(), state.angle.sin()) * state.drive)
+    }
+
+    /// Modules whose sensors could be read on the last command
+    pub fn healthy_module_count(&self) -> usize {
+        self.module_healthy
+            .iter()
+            .filter(|healthy| **healthy)
+            .count()
+    }
+
+    /// Sets how the drivetrain behaves while a module is unhealthy
+    pub fn set_degraded_policy(&mut self, policy: DegradedPolicy) {
+        self.degraded_policy = policy;
+    }
+
+    /// Reads every module, stopping and logging any that fail and logging any that recover
+    fn check_module_health(&mut self) {
+        for (index, module) in self.modules.iter_mut().enumerate() {
+            let healthy = module.get_state().is_ok();
+
+            if healthy != self.module_healthy[index] {
+                if healthy {
+                    info!("The {} module has recovered", MODULE_NAMES[index]);
+                } else {
+                    warn!(
+                        "The {} module is not responding, driving degraded with {:?}",
+                        MODULE_NAMES[index], self.degraded_policy
+                    );
+                }
+            }
+
+            if !healthy {
+                module.stop();
+            }
+
+            self.module_healthy[index] = healthy;
+        }
+    }
+
+    /// Volts per meter per second squared of acceleration feedforward. Zero disables it.
+    pub fn set_drive_ka(&mut self, ka: f32) {
+        self.drive_ka = ka;
+    }
+
+    /// Meters per second added to each module's velocity setpoint so it leads the commanded
+    /// chassis acceleration (robot relative vx, vy, omega per second). The acceleration each
+    /// module sees is projected onto the direction it is being driven in, and `drive_ka` is
+    /// converted from volts to a velocity setpoint through `DRIVE_KV`. Constant velocity commands
+    /// have no acceleration, so they get no feedforward.
+    fn accel_feedforward(&self, states: &[SwerveState; 4], accel: Vector3<f32>) -> [f32; 4] {
+        if self.drive_ka == 0.0 {
+            return [0.0; 4];
         }
+
+        let module_accels = self.kinematics.inverse(accel);
+        let mut feedforward = [0.0; 4];
+
+        for ((feedforward, state), module_accel) in
+            feedforward.iter_mut().zip(states).zip(module_accels)
+        {
+            let along = module_accel.drive * (module_accel.angle - state.angle).cos();
+            *feedforward = self.drive_ka / DRIVE_KV * along;
+        }
+
+        feedforward
+    }
+
+    /// The robot relative speeds most recently passed to the kinematics, after slew limiting,
+    /// heading limits and the field to robot rotation. Zero while the speed deadband is holding
+    /// the modules.
+    pub fn get_commanded_chassis_speeds(&self) -> ChassisSpeeds {
+        self.commanded_speeds
+    }
+
+    /// When enabled, the drive setpoint of any wheel spinning faster than the measured chassis
+    /// motion explains is limited to just above the speed it should be turning at
+    pub fn set_traction_control(&mut self, enabled: bool) {
+        self.traction_control = enabled;
+    }
+
+    /// Largest drive speed (m/s) each module may be commanded without exceeding the slip ratio
+    /// threshold, or `None` if the modules couldn't be read.
+    ///
+    /// The measured module states are projected onto the closest rigid body chassis motion and
+    /// each wheel is compared with the speed that motion implies. If every wheel slips together
+    /// the chassis estimate moves with them, so the limits relax instead of fighting each other.
+    fn traction_limits(&mut self) -> Option<[f32; 4]> {
+        let mut measured = [SwerveState::new(0.0, 0.0); 4];
+
+        for (state, module) in measured.iter_mut().zip(&mut self.modules) {
+            *state = module.get_state().ok()?;
+        }
+
+        let expected = self.kinematics.inverse(self.kinematics.forward(measured));
+
+        Some(expected.map(|state| state.drive.abs() * (1.0 + MAX_SLIP_RATIO) + TRACTION_MIN_SPEED))
+    }
+
+    /// When enabled, pure translation commands get a corrective turn rate against the measured
+    /// rotation rate, keeping the robot from yawing during fast strafes
+    pub fn set_rotation_compensation(&mut self, enabled: bool) {
+        self.rotation_compensation = enabled;
+    }
+
+    /// Radians per second of correction per radian per second of measured rotation
+    pub fn set_rotation_compensation_gain(&mut self, gain: f32) {
+        self.rotation_compensation_gain = gain;
+    }
+
+    fn compensate_rotation(&self, drive: Vector2<f32>, turn_rate: f32) -> f32 {
+        if self.rotation_compensation
+            && turn_rate.abs() < ROTATION_COMPENSATION_THRESHOLD
+            && drive != Vector2::zeros()
+        {
+            turn_rate - self.rotation_compensation_gain * self.get_turn_rate()
+        } else {
+            turn_rate
+        }
+    }
+
+    /// Measured rotation rate in radians per second, counterclockwise positive
+    pub fn get_turn_rate(&self) -> f32 {
+        (-Radians::from(Degrees(self.gyro.rate()))).0
+    }
+
+    /// Whether the measured chassis speed (m/s, from forward kinematics) or gyro rate (rad/s) is
+    /// above its threshold. `MOVING_LINEAR_THRESHOLD` and `MOVING_ANGULAR_THRESHOLD` are small
+    /// defaults that sit just above sensor noise.
+    pub fn is_moving(
+        &mut self,
+        linear_threshold: f32,
+        angular_threshold: f32,
+    ) -> anyhow::Result<bool> {
+        let mut measured = [SwerveState::new(0.0, 0.0); 4];
+
+        for (state, module) in measured.iter_mut().zip(&mut self.modules) {
+            *state = module.get_state()?;
+        }
+
+        let speeds = self.kinematics.forward(measured);
+
+        Ok(speeds.xy().norm() > linear_threshold || self.get_turn_rate().abs() > angular_threshold)
+    }
+
+    /// Radians, positive when the front of the robot is tilted up
+    pub fn get_pitch(&self) -> f32 {
+        Radians::from(Degrees(self.gyro.pitch())).0
+    }
+
+    /// Radians, positive when the left side of the robot is tilted up
+    pub fn get_roll(&self) -> f32 {
+        Radians::from(Degrees(self.gyro.roll())).0
+    }
+
+    /// Whether the gyro has finished calibrating. Until it has, field oriented commands are
+    /// treated as robot relative.
+    pub fn gyro_ready(&self) -> bool {
+        !self.gyro.is_calibrating()
+    }
+
+    /// Resolves once the gyro has finished calibrating. Doesn't borrow the drivetrain, so it can be
+    /// awaited without holding the subsystem lock.
+    pub fn wait_for_gyro(&self) -> impl Future<Output = ()> + 'static {
+        let gyro = self.gyro.clone();
+
+        async move {
+            while gyro.is_calibrating() {
+                yield_now().await;
+            }
+        }
+    }
+
+    pub fn get_heading(&self) -> f32 {
+        normalize_angle(gyro_heading(&*self.gyro, self.heading_offset.get()))
+    }
+
+    /// Below this commanded speed (m/s for translation, rad/s for rotation) the modules keep their
+    /// current angles with zero drive rather than steering toward a noisy target
+    pub fn set_speed_deadband(&mut self, deadband: f32) {
+        self.speed_deadband = deadband;
+    }
+
+    /// Restricts the heading to the arc running counterclockwise from `min` to `max` (radians).
+    /// Turn commands are clamped so the robot stops at the boundary, and if it is already outside
+    /// the arc it may only turn back toward the nearest boundary.
+    pub fn set_heading_limits(&mut self, limits: Option<(f32, f32)>) {
+        self.heading_limits = limits;
+    }
+
+    fn limit_turn_rate(&self, turn_rate: f32) -> f32 {
+        let Some((min, max)) = self.heading_limits else {
+            return turn_rate;
+        };
+
+        let heading = self.get_heading();
+        let dt = self.loop_period().as_secs_f32();
+
+        let to_max = (max - heading).rem_euclid(TAU);
+        let to_min = (heading - min).rem_euclid(TAU);
+
+        if to_max + to_min <= (max - min).rem_euclid(TAU) + f32::EPSILON {
+            turn_rate.clamp(-to_min / dt, to_max / dt)
+        } else if (heading - max).rem_euclid(TAU) < (min - heading).rem_euclid(TAU) {
+            turn_rate.min(0.0)
+        } else {
+            turn_rate.max(0.0)
+        }
+    }
+
+    /// Applies the circular deadband to a normalized translation input, rescaling so full stick
+    /// is still full speed
+    pub fn apply_deadband(&self, drive: Vector2<f32>) -> Vector2<f32> {
+        radial_deadband(drive, self.translation_deadband)
+    }
+
+    pub fn translation_deadband(&self) -> f32 {
+        self.translation_deadband
+    }
+
+    /// Radius of the translation deadband as a fraction of full stick, in [0, 1)
+    pub fn set_translation_deadband(&mut self, radius: f32) -> anyhow::Result<()> {
+        ensure!(
+            (0.0..1.0).contains(&radius),
+            "Translation deadband must be in [0, 1), got {radius}"
+        );
+
+        self.translation_deadband = radius;
+
+        Ok(())
+    }
+
+    /// Below this speed (m/s) an individual module keeps its previous angle rather than steering,
+    /// e.g. a module sitting on the center of rotation
+    pub fn set_module_steer_deadband(&mut self, deadband: f32) {
+        for module in &mut self.modules {
+            module.set_steer_deadband(deadband);
+        }
+    }
+
+    /// See `SwerveModule::set_optimize_threshold`. Defaults to PI / 2.
+    pub fn set_module_optimize_threshold(&mut self, threshold: f32) -> anyhow::Result<()> {
+        for module in &mut self.modules {
+            module.set_optimize_threshold(threshold)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the heading the robot is currently trying to hold, used for heading error tracking
+    pub fn set_heading_setpoint(&mut self, heading: Option<f32>) {
+        self.heading_setpoint = heading;
+    }
+
+    /// RMS heading error in radians over every loop with an active heading setpoint since the
+    /// last reset, or `None` if no samples have been taken
+    pub fn heading_error_rms(&self) -> Option<f32> {
+        self.heading_error.rms()
+    }
+
+    pub fn reset_heading_error(&mut self) {
+        self.heading_error = HeadingErrorStats::default();
+    }
+
+    /// Limits the rate of change of translation acceleration (normalized input per second squared),
+    /// or only limits acceleration when `None`
+    pub fn set_max_jerk(&mut self, jerk: Option<f32>) {
+        self.x_limit.set_jerk_limit(jerk);
+        self.y_limit.set_jerk_limit(jerk);
+    }
+
+    /// Overrides the loop period used by the slew limiters. Passing `None` returns to the
+    /// measured period.
+    pub fn set_loop_period(&mut self, period: Option<Duration>) {
+        self.loop_period_override = period;
+    }
+
+    /// The loop period used by everything that integrates over time in the drivetrain. Unless
+    /// overridden, this is the time between the last two calls to `set_input`, falling back to
+    /// 20ms for the first call and after gaps longer than 100ms.
+    pub fn loop_period(&self) -> Duration {
+        self.loop_period_override
+            .unwrap_or(self.measured_loop_period)
+    }
+
+    /// Drive feedforward in volts for the next loop when moving from `velocity` toward
+    /// `target_velocity` (m/s) while accelerating at no more than `max_accel` (m/s^2).
+    ///
+    /// The acceleration is worked out per second and only then multiplied by `loop_period`, so
+    /// the same profile produces the same physical response at any loop rate.
+    pub fn scale_to_voltage(&self, velocity: f32, target_velocity: f32, max_accel: f32) -> f32 {
+        let dt = self.loop_period().as_secs_f32();
+
+        let accel = ((target_velocity - velocity) / dt).clamp(-max_accel, max_accel);
+        let next_velocity = velocity + accel * dt;
+
+        DRIVE_KV * next_velocity + self.drive_ka * accel
+    }
+
+    fn measure_loop_period(&mut self) -> f32 {
+        let now = Instant::now();
+
+        self.measured_loop_period = match self.last_loop {
+            Some(last) if now - last <= MAX_LOOP_PERIOD => now - last,
+            _ => NOMINAL_LOOP_PERIOD,
+        };
+        self.last_loop = Some(now);
+
+        self.loop_period().as_secs_f32()
+    }
+
+    /// When enabled, holding zero input for `delay` puts the drivetrain into the X-stance brake.
+    /// Any nonzero input cancels a pending brake immediately.
+    pub fn set_auto_brake(&mut self, enabled: bool, delay: Duration) {
+        self.auto_brake_delay = enabled.then_some(delay);
+        self.released_since = None;
+    }
+
+    fn should_auto_brake(&mut self, released: bool) -> bool {
+        let Some(delay) = self.auto_brake_delay else {
+            return false;
+        };
+
+        if !released {
+            self.released_since = None;
+            return false;
+        }
+
+        self.released_since
+            .get_or_insert_with(Instant::now)
+            .elapsed()
+            >= delay
+    }
+
+    /// Slew limits a normalized command, returning it in meters per second and radians per second
+    fn limit(&mut self, drive: Vector2<f32>, turn_rate: f32) -> (Vector2<f32>, f32) {
+        let dt = self.measure_loop_period();
+
+        let drive = Vector2::new(
+            self.x_limit.apply(drive.x, dt),
+            self.y_limit.apply(drive.y, dt),
+        )
+        .scale(MAX_VELOCITY_LIMIT);
+        let turn_rate = self.angle_limit.apply(turn_rate, dt) * MAX_ROTATION_LIMIT;
+
+        match self.soft_start_cap() {
+            Some(cap) => (
+                drive.cap_magnitude(MAX_VELOCITY_LIMIT * cap),
+                turn_rate.clamp(-MAX_ROTATION_LIMIT * cap, MAX_ROTATION_LIMIT * cap),
+            ),
+            None => (drive, turn_rate),
+        }
+    }
+
+    /// When set, the speed limits ramp linearly from a quarter of full up to full over `duration`
+    /// after each enable, softening the shock of everything starting at once. Unlike the
+    /// slew limiters this caps the top speed rather than the rate of change.
+    pub fn set_soft_start(&mut self, duration: Option<Duration>) {
+        self.soft_start = duration;
+    }
+
+    /// Moves the drivetrain between enabled and disabled, running every transition in one place.
+    ///
+    /// Enabling restarts the soft start ramp, zeroes the slew limiters and restores drive braking.
+    /// Disabling stops the modules, drops any heading hold, pending auto brake and commanded
+    /// speeds, and lets the drive motors coast if `set_coast_on_disable` is on. Commands sent
+    /// while disabled are ignored rather than queued, so nothing from before the disable is
+    /// replayed on the next enable. A drivetrain starts out enabled.
+    pub fn set_enabled(&mut self, enabled: bool) -> anyhow::Result<()> {
+        self.enabled = enabled;
+        self.reset_slew_limiters();
+
+        if enabled {
+            self.enabled_at = Some(Instant::now());
+        } else {
+            self.heading_setpoint = None;
+            self.released_since = None;
+            self.commanded_speeds = ChassisSpeeds::default();
+            self.stop();
+        }
+
+        let coast = !enabled && self.coast_on_disable;
+        for module in &mut self.modules {
+            module.set_drive_coast(coast)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Stops every module and latches, ignoring drive commands and brakes until
+    /// `clear_emergency_stop`. Unlike `stop`, a stale teleop command on the next loop can't
+    /// undo it, so use this when a fault like a collision, stall or gyro loss should keep the
+    /// robot still until someone looks at it.
+    pub fn emergency_stop(&mut self) {
+        if !self.emergency_stopped {
+            error!("Drivetrain emergency stopped");
+        }
+
+        self.emergency_stopped = true;
+        self.heading_setpoint = None;
+        self.commanded_speeds = ChassisSpeeds::default();
+        self.stop();
+    }
+
+    /// Releases the emergency stop latch. The slew limiters restart from rest, so the next
+    /// command ramps up rather than jumping.
+    pub fn clear_emergency_stop(&mut self) {
+        if self.emergency_stopped {
+            info!("Drivetrain emergency stop cleared");
+        }
+
+        self.emergency_stopped = false;
+        self.reset_slew_limiters();
+    }
+
+    pub fn is_emergency_stopped(&self) -> bool {
+        self.emergency_stopped
+    }
+
+    /// Errors while emergency stopped, for commands and routines that move the modules outside
+    /// the normal drive path
+    fn ensure_not_emergency_stopped(&self) -> anyhow::Result<()> {
+        ensure!(
+            !self.emergency_stopped,
+            "The drivetrain is emergency stopped, clear it before moving the modules"
+        );
+
+        Ok(())
+    }
+
+    /// When enabled, the drive motors coast while the robot is disabled so it can be pushed by
+    /// hand
+    pub fn set_coast_on_disable(&mut self, enabled: bool) {
+        self.coast_on_disable = enabled;
+    }
+
+    /// Fraction of the speed limits currently allowed, `None` without a soft start
+    fn soft_start_cap(&self) -> Option<f32> {
+        let (Some(duration), Some(enabled_at)) = (self.soft_start, self.enabled_at) else {
+            return None;
+        };
+
+        let progress = if duration.is_zero() {
+            1.0
+        } else {
+            (enabled_at.elapsed().as_secs_f32() / duration.as_secs_f32()).min(1.0)
+        };
+
+        Some(SOFT_START_INITIAL + (1.0 - SOFT_START_INITIAL) * progress)
+    }
+
+    /// Zeroes the slew limiters so the next command ramps from rest rather than from whatever an
+    /// earlier mode left behind. Call this on mode changes, like entering teleop after auto or
+    /// switching between driver assists, so the handoff neither jumps nor lags.
+    pub fn reset_slew_limiters(&mut self) {
+        self.x_limit.reset(0.0);
+        self.y_limit.reset(0.0);
+        self.angle_limit.reset(0.0);
+        self.last_loop = None;
+    }
+
+    /// When enabled, letting the stick fall into its deadband stops that motion immediately
+    /// instead of ramping down through the slew limiters. Translation and rotation are handled
+    /// separately. Off by default.
+    pub fn set_instant_stop_on_release(&mut self, enabled: bool) {
+        self.instant_stop_on_release = enabled;
+    }
+
+    pub fn set_input(&mut self, drive: Vector2<f32>, turn_rate: f32) -> anyhow::Result<()> {
+        if let Some((start, samples)) = &mut self.input_recording {
+            samples.push(InputSample {
+                time: start.elapsed().as_secs_f32(),
+                drive,
+                turn_rate,
+            });
+        }
+
+        self.input(drive, turn_rate, true)
+    }
+
+    /// Starts recording every `set_input` call, discarding any recording in progress
+    pub fn start_input_recording(&mut self) {
+        self.input_recording = Some((Instant::now(), Vec::new()));
+    }
+
+    /// Ends the recording, returning the inputs since `start_input_recording` for
+    /// `replay_inputs`, or `None` if nothing was recording. Save it with `InputTrace::to_csv`.
+    pub fn stop_input_recording(&mut self) -> Option<InputTrace> {
+        let (_, samples) = self.input_recording.take()?;
+
+        // Samples are timestamped as they arrive, so they are always in order
+        InputTrace::new(samples).ok()
+    }
+
+    /// Driver input path shared by `set_input` and `set_input_with_frames`: instant stop on
+    /// release, slew limiting and auto brake, then the command itself
+    fn input(
+        &mut self,
+        drive: Vector2<f32>,
+        turn_rate: f32,
+        field_relative: bool,
+    ) -> anyhow::Result<()> {
+        let released = drive == Vector2::zeros() && turn_rate == 0.0;
+
+        if self.nt_command_mode {
+            // Released sticks would command a stop over the top of the bench commands
+            if released {
+                return Ok(());
+            }
+
+            info!("Driver input received, leaving NetworkTables command mode");
+            self.nt_command_mode = false;
+        }
+
+        if self.instant_stop_on_release {
+            if drive == Vector2::zeros() {
+                self.x_limit.reset(0.0);
+                self.y_limit.reset(0.0);
+            }
+
+            if turn_rate == 0.0 {
+                self.angle_limit.reset(0.0);
+            }
+        }
+
+        let (drive, turn_rate) = self.limit(drive, turn_rate);
+
+        if self.should_auto_brake(released) {
+            return self.brake();
+        }
+
+        self.command(drive, turn_rate, field_relative, None)
+    }
+
+    /// Like `set_input`, but with the translation and rotation frames chosen separately. A robot
+    /// relative `rotation` is a normalized turn rate as in `set_input`. A turn rate is the same in
+    /// either frame, so a field relative `rotation` is instead an absolute field heading in
+    /// radians for the heading controller to turn to.
+    pub fn set_input_with_frames(
+        &mut self,
+        drive: Vector2<f32>,
+        translation_frame: InputFrame,
+        rotation: f32,
+        rotation_frame: InputFrame,
+    ) -> anyhow::Result<()> {
+        let turn_rate = match rotation_frame {
+            InputFrame::Robot => {
+                self.heading_setpoint = None;
+                rotation
+            }
+            InputFrame::Field => {
+                self.heading_setpoint = Some(rotation);
+                self.heading_hold_output(rotation) / MAX_ROTATION_LIMIT
+            }
+        };
+
+        self.input(drive, turn_rate, translation_frame == InputFrame::Field)
+    }
+
+    /// Commands field relative chassis speeds.
+    ///
+    /// With `limited` the speeds pass through the same slew limiters as teleop, which suits
+    /// simple setpoint moves like driving to a pose. Trajectory following should pass `false`,
+    /// since the trajectory's feedforward already respects acceleration limits and the limiter
+    /// would only add lag.
+    pub fn set_chassis_speeds(
+        &mut self,
+        speeds: ChassisSpeeds,
+        limited: bool,
+    ) -> anyhow::Result<()> {
+        self.command_chassis_speeds(speeds, limited, true, None)
+    }
+
+    /// Like `set_chassis_speeds`, but discretizes over an explicit control period instead of the
+    /// measured loop period, for controllers running at a known rate different from teleop
+    pub fn set_chassis_speeds_with_period(
+        &mut self,
+        speeds: ChassisSpeeds,
+        limited: bool,
+        period: Duration,
+    ) -> anyhow::Result<()> {
+        self.command_chassis_speeds(speeds, limited, true, Some(period))
+    }
+
+    /// Commands robot relative chassis speeds (x forward, y left), skipping the field to robot
+    /// rotation. Use this for controllers that already work in the robot frame; passing their
+    /// output to `set_chassis_speeds` would rotate it by the heading a second time. `limited`
+    /// works as in `set_chassis_speeds`.
+    pub fn set_chassis_speeds_robot_relative(
+        &mut self,
+        speeds: ChassisSpeeds,
+        limited: bool,
+    ) -> anyhow::Result<()> {
+        self.command_chassis_speeds(speeds, limited, false, None)
+    }
+
+    fn command_chassis_speeds(
+        &mut self,
+        speeds: ChassisSpeeds,
+        limited: bool,
+        field_relative: bool,
+        period: Option<Duration>,
+    ) -> anyhow::Result<()> {
+        let (drive, turn_rate) = if limited {
+            self.limit(
+                speeds.velocity() / MAX_VELOCITY_LIMIT,
+                speeds.omega / MAX_ROTATION_LIMIT,
+            )
+        } else {
+            self.measure_loop_period();
+            (speeds.velocity(), speeds.omega)
+        };
+
+        self.command(drive, turn_rate, field_relative, period)
+    }
+
+    /// Drives forward along a circular arc of `radius` meters at `speed` meters per second. If
+    /// the speed, or the turn rate it takes on an arc this tight, is past the speed limits, the
+    /// speed is lowered until both fit so the robot still follows the same arc.
+    pub fn drive_arc(&mut self, radius: f32, speed: f32) -> anyhow::Result<()> {
+        ensure!(
+            radius.is_finite() && radius != 0.0,
+            "Arc radius must be finite and nonzero, got {radius}"
+        );
+
+        self.set_chassis_speeds_robot_relative(arc_speeds(radius, speed), true)
+    }
+
+    /// Turn rate in radians per second that drives the heading toward `target_heading`, from the
+    /// holonomic controller's heading PID stepped over the loop period
+    pub fn heading_hold_output(&mut self, target_heading: f32) -> f32 {
+        let max = self.max_heading_output();
+        let error = self.heading_error_to(target_heading);
+
+        self.holonomic_controller
+            .theta_pid
+            .calculate(error, self.loop_period().as_secs_f32())
+            .clamp(-max, max)
+    }
+
+    /// Drives at `speed` meters per second in a field direction given in radians counterclockwise
+    /// from field forward for `duration`, then stops. The heading it started at is held against
+    /// bumps the whole way. Only the gyro heading is used, never the x/y odometry, so this makes
+    /// dead reckoning autos that odometry drift can't throw off.
+    pub async fn drive_heading(
+        &mut self,
+        field_direction: f32,
+        speed: f32,
+        duration: Duration,
+    ) -> anyhow::Result<()> {
+        let velocity = Vector2::new(field_direction.cos(), field_direction.sin()) * speed;
+        let heading = self.get_heading();
+        let start = Instant::now();
+
+        while start.elapsed() < duration {
+            self.set_velocity_and_heading(velocity, heading)?;
+
+            yield_now().await;
+        }
+
+        self.heading_setpoint = None;
+        self.set_chassis_speeds(ChassisSpeeds::default(), false)
+    }
+
+    /// Drives at a field relative velocity in meters per second while the heading controller
+    /// turns to and holds `target_heading`, for controllers that produce a heading rather than a
+    /// turn rate
+    pub fn set_velocity_and_heading(
+        &mut self,
+        velocity: Vector2<f32>,
+        target_heading: f32,
+    ) -> anyhow::Result<()> {
+        self.heading_setpoint = Some(target_heading);
+        let omega = self.heading_hold_output(target_heading);
+
+        self.set_chassis_speeds(ChassisSpeeds::new(velocity.x, velocity.y, omega), true)
+    }
+
+    /// Replaces the pose and heading feedback used by `drive_to_pose`, `nudge`,
+    /// `turn_to_heading`, trajectory following and every heading hold. The default is
+    /// proportional only.
+    pub fn set_holonomic_controller(&mut self, controller: HolonomicController) {
+        self.holonomic_controller = controller;
+    }
+
+    /// Caps the heading controller at a fraction of the max rotation speed, leaving the rest of
+    /// the wheels' speed for translation so aiming while driving doesn't stall the robot to
+    /// finish a turn
+    pub fn set_heading_output_fraction(&mut self, fraction: f32) -> anyhow::Result<()> {
+        ensure!(
+            fraction > 0.0 && fraction <= 1.0,
+            "Heading output fraction must be in (0, 1], got {fraction}"
+        );
+
+        self.heading_output_fraction = fraction;
+
+        Ok(())
+    }
+
+    fn max_heading_output(&self) -> f32 {
+        MAX_ROTATION_LIMIT * self.heading_output_fraction
+    }
+
+    /// Limits on the heading setpoint ramp used by `turn_to_heading`, in radians per second and
+    /// radians per second squared
+    pub fn set_heading_profile_limits(
+        &mut self,
+        max_velocity: f32,
+        max_accel: f32,
+    ) -> anyhow::Result<()> {
+        self.heading_profile = TrapezoidProfile::new(max_velocity, max_accel)?;
+
+        Ok(())
+    }
+
+    /// Turns in place to `heading`, taking the short way around. Rather than feeding the final
+    /// heading straight into the heading controller, the setpoint is ramped toward it along a
+    /// trapezoidal profile so large turns start and stop smoothly.
+    ///
+    /// Only the gyro is used, so this works without odometry. Errors if the heading isn't
+    /// reached within `TURN_TO_HEADING_TIMEOUT`, e.g. with the robot wedged against something.
+    pub async fn turn_to_heading(&mut self, heading: f32) -> anyhow::Result<()> {
+        let start = Instant::now();
+        let mut setpoint = ProfileState {
+            position: self.get_heading(),
+            velocity: 0.0,
+        };
+        // Unwrapped, so the profile doesn't have to deal with the wrap at PI
+        let goal = setpoint.position + self.heading_error_to(heading);
+        self.holonomic_controller.reset();
+
+        loop {
+            if setpoint.position == goal
+                && self.heading_error_to(heading).abs() <= POSE_HEADING_TOLERANCE
+            {
+                break;
+            }
+
+            if start.elapsed() >= TURN_TO_HEADING_TIMEOUT {
+                self.heading_setpoint = None;
+                self.stop();
+
+                bail!(
+                    "Turn to heading timed out {:.3} rad short",
+                    self.heading_error_to(heading)
+                );
+            }
+
+            setpoint = self
+                .heading_profile
+                .step(setpoint, goal, self.loop_period().as_secs_f32());
+            self.heading_setpoint = Some(wrap_angle(setpoint.position));
+
+            // Only the heading is controlled, so the pose is the gyro heading alone
+            let omega = self
+                .holonomic_controller
+                .calculate(
+                    Vector3::new(0.0, 0.0, self.get_heading()),
+                    Vector3::new(0.0, 0.0, setpoint.position),
+                    Vector3::new(0.0, 0.0, setpoint.velocity),
+                    self.loop_period().as_secs_f32(),
+                )
+                .omega
+                .clamp(-MAX_ROTATION_LIMIT, MAX_ROTATION_LIMIT);
+
+            self.set_chassis_speeds(ChassisSpeeds::new(0.0, 0.0, omega), false)?;
+
+            yield_now().await;
+        }
+
+        self.heading_setpoint = None;
+        self.set_chassis_speeds(ChassisSpeeds::default(), false)
+    }
+
+    /// Sets how close (radians) the heading must be to a cardinal direction for
+    /// `wall_align_strafe` to square up to it
+    pub fn set_cardinal_snap_tolerance(&mut self, tolerance: f32) {
+        self.cardinal_snap_tolerance = tolerance;
+    }
+
+    /// Strafes robot relative left (positive) or right at `speed` meters per second while holding
+    /// the nearest of the 0/90/180/270 degree headings. Outside the snap tolerance no heading
+    /// correction is applied.
+    pub fn wall_align_strafe(&mut self, speed: f32) -> anyhow::Result<()> {
+        let heading = self.get_heading();
+        let cardinal = (heading / FRAC_PI_2).round() * FRAC_PI_2;
+
+        let turn_rate = if wrap_angle(cardinal - heading).abs() <= self.cardinal_snap_tolerance {
+            self.heading_setpoint = Some(cardinal);
+            self.heading_hold_output(cardinal)
+        } else {
+            self.heading_setpoint = None;
+            0.0
+        };
+
+        let velocity = self.robot_to_field(Vector2::new(0.0, speed));
+
+        self.set_chassis_speeds(ChassisSpeeds::new(velocity.x, velocity.y, turn_rate), true)
+    }
+
+    /// When enabled, trajectory following and `drive_to_pose` brake and return
+    /// `CollisionDetected` as soon as the gyro measures a horizontal acceleration spike, instead
+    /// of grinding against whatever was hit for the rest of the move. Toggling it keeps the
+    /// threshold.
+    pub fn set_collision_abort(&mut self, enabled: bool) {
+        self.collision_abort = enabled;
+    }
+
+    /// G of horizontal acceleration that counts as a collision, enabling collision abort
+    pub fn set_collision_threshold(&mut self, g: f32) {
+        self.collision_threshold = g;
+        self.collision_abort = true;
+    }
+
+    fn check_collision(&mut self) -> anyhow::Result<()> {
+        if !self.collision_abort {
+            return Ok(());
+        }
+
+        let acceleration = self.gyro.acceleration().norm();
+        if acceleration < self.collision_threshold {
+            return Ok(());
+        }
+
+        warn!("Collision detected ({acceleration:.2}g), braking");
+        self.heading_setpoint = None;
+        self.brake()?;
+
+        Err(CollisionDetected { acceleration }.into())
+    }
+
+    /// Fastest the chassis can translate in meters per second: every wheel at the drive free
+    /// speed. This is the free speed `DRIVE_F` is derived from, so real robots under load fall
+    /// somewhat short of it.
+    pub fn max_translational_speed(&self) -> f32 {
+        DRIVE_FREE_SPEED
+    }
+
+    /// Fastest the chassis can spin in place in radians per second: the module farthest from the
+    /// robot center at the drive free speed, tangent to its circle around the center
+    pub fn max_angular_speed(&self) -> f32 {
+        let radius = self
+            .module_positions
+            .iter()
+            .map(|position| position.norm())
+            .fold(0.0, f32::max);
+
+        DRIVE_FREE_SPEED / radius
+    }
+
+    /// Slows a trajectory wherever it would ask any module to drive faster than `max_speed` (m/s)
+    pub fn limit_trajectory(
+        &self,
+        trajectory: &Trajectory,
+        max_speed: f32,
+    ) -> anyhow::Result<Trajectory> {
+        trajectory.limit_module_speeds(&self.kinematics, max_speed)
+    }
+
+    /// Time left on the trajectory being followed, or `None` when not following one
+    pub fn trajectory_time_remaining(&self) -> Option<Duration> {
+        self.trajectory_end
+            .get()
+            .map(|end| end.saturating_duration_since(Instant::now()))
+    }
+
+    /// Follows a trajectory to completion, then stops
+    pub async fn follow_trajectory(&mut self, trajectory: &Trajectory) -> anyhow::Result<()> {
+        self.follow_trajectory_with_events(trajectory, Vec::new())
+            .await
+    }
+
+    /// Follows a trajectory, firing each event once, in trigger order, as the follower passes it.
+    /// Any events not yet fired when the trajectory ends fire before this returns.
+    pub async fn follow_trajectory_with_events(
+        &mut self,
+        trajectory: &Trajectory,
+        mut events: Vec<TrajectoryEvent<'_>>,
+    ) -> anyhow::Result<()> {
+        events.sort_by(|a, b| {
+            a.trigger
+                .time(trajectory)
+                .total_cmp(&b.trigger.time(trajectory))
+        });
+        let mut events = events.into_iter().peekable();
+
+        if self.pre_align {
+            let first_motion = trajectory
+                .samples()
+                .iter()
+                .find(|sample| sample.velocity != Vector3::zeros())
+                .map_or(Vector3::zeros(), |sample| sample.velocity);
+
+            self.align_modules(first_motion.xy(), first_motion.z)
+                .await?;
+        }
+
+        self.holonomic_controller.reset();
+        let start = Instant::now();
+
+        self.trajectory_end.set(Some(
+            start + Duration::from_secs_f32(trajectory.total_time()),
+        ));
+        let trajectory_end = self.trajectory_end.clone();
+        let _following = OnDrop::new(move || trajectory_end.set(None));
+
+        loop {
+            let time = start.elapsed().as_secs_f32();
+            let target = trajectory.sample(time);
+
+            while let Some(mut event) =
+                events.next_if(|event| event.trigger.time(trajectory) <= time)
+            {
+                (event.callback)();
+            }
+
+            if time >= trajectory.total_time() {
+                break;
+            }
+
+            self.ensure_not_emergency_stopped()?;
+            self.check_collision()?;
+            self.ensure_odometry_fresh()?;
+
+            self.heading_setpoint = Some(target.pose.z);
+            let speeds = self.holonomic_controller.calculate(
+                self.get_pose(),
+                target.pose,
+                target.velocity,
+                self.loop_period().as_secs_f32(),
+            );
+
+            self.set_chassis_speeds(speeds, false)?;
+
+            yield_now().await;
+        }
+
+        for mut event in events {
+            (event.callback)();
+        }
+
+        self.heading_setpoint = None;
+        self.set_chassis_speeds(ChassisSpeeds::default(), false)
+    }
+
+    /// When enabled, trajectory following and `drive_to_pose` first steer every module to the
+    /// angle the move starts with and wait for them to get there before driving, preventing
+    /// scrub at the start of short precise moves. On by default.
+    pub fn set_pre_align(&mut self, enabled: bool) {
+        self.pre_align = enabled;
+    }
+
+    /// Steers the modules to the angles needed for the given field relative motion with zero
+    /// drive, resolving once all are within tolerance or the timeout passes
+    async fn align_modules(&mut self, velocity: Vector2<f32>, omega: f32) -> anyhow::Result<()> {
+        self.ensure_not_emergency_stopped()?;
+
+        let velocity = self.field_to_robot(velocity);
+
+        if velocity.norm() < self.speed_deadband && omega.abs() < self.speed_deadband {
+            return Ok(());
+        }
+
+        let states = self.kinematics.inverse(velocity.fixed_resize(omega));
+        let start = Instant::now();
+
+        loop {
+            let mut aligned = true;
+
+            for (module, state) in self.modules.iter_mut().zip(states) {
+                module.set_target_steered(SwerveState::new(state.get_angle(), 0.0))?;
+                aligned &= module.at_angle_target(PRE_ALIGN_TOLERANCE)?;
+            }
+
+            if aligned {
+                return Ok(());
+            }
+
+            if start.elapsed() >= PRE_ALIGN_TIMEOUT {
+                warn!("Modules did not align before starting to drive");
+                return Ok(());
+            }
+
+            yield_now().await;
+        }
+    }
+
+    /// Drives to a field pose, finishing once within `tolerance` meters of it and facing its
+    /// heading. Errors if the drivetrain is emergency stopped, since it would never get there.
+    pub async fn drive_to_pose(
+        &mut self,
+        target: Vector3<f32>,
+        tolerance: f32,
+    ) -> anyhow::Result<()> {
+        self.pose_target = Some(target);
+
+        if self.pre_align {
+            self.holonomic_controller.reset();
+            let speeds = self.holonomic_controller.calculate(
+                self.get_pose(),
+                target,
+                Vector3::zeros(),
+                self.loop_period().as_secs_f32(),
+            );
+
+            self.align_modules(speeds.velocity(), speeds.omega).await?;
+        }
+
+        self.heading_setpoint = Some(target.z);
+        self.holonomic_controller.reset();
+
+        loop {
+            let error = target.xy() - self.get_pose().xy();
+
+            if error.norm() <= tolerance
+                && self.heading_error_to(target.z).abs() <= POSE_HEADING_TOLERANCE
+            {
+                break;
+            }
+
+            self.ensure_not_emergency_stopped()?;
+            self.check_collision()?;
+            self.ensure_odometry_fresh()?;
+
+            let speeds = self.holonomic_controller.calculate(
+                self.get_pose(),
+                target,
+                Vector3::zeros(),
+                self.loop_period().as_secs_f32(),
+            );
+            let velocity = speeds.velocity().cap_magnitude(MAX_VELOCITY_LIMIT);
+            let max_omega = self.max_heading_output();
+            let omega = speeds.omega.clamp(-max_omega, max_omega);
+
+            self.set_chassis_speeds(ChassisSpeeds::new(velocity.x, velocity.y, omega), true)?;
+
+            yield_now().await;
+        }
+
+        self.heading_setpoint = None;
+        self.set_chassis_speeds(ChassisSpeeds::default(), false)
+    }
+
+    /// Resolves once every module has been within `angle_tolerance` radians of its commanded
+    /// angle and `velocity_tolerance` meters per second of its commanded speed for a short while,
+    /// along with the pose within `pose_tolerance` meters of the last `drive_to_pose` target if
+    /// there has been one since the pose was reset. Keeps nothing commanded itself, so it is
+    /// meant for sequencing after a move finishes.
+    pub async fn wait_until_settled(
+        &mut self,
+        angle_tolerance: f32,
+        velocity_tolerance: f32,
+        pose_tolerance: f32,
+    ) -> anyhow::Result<()> {
+        let mut settled_since = None;
+
+        loop {
+            let mut settled = self
+                .drive_velocity_errors()?
+                .iter()
+                .all(|error| error.abs() <= velocity_tolerance);
+
+            for module in &mut self.modules {
+                settled &= module.at_angle_target(angle_tolerance)?;
+            }
+
+            if let Some(target) = self.pose_target {
+                settled &= self.distance_to(target.xy()) <= pose_tolerance;
+            }
+
+            if !settled {
+                settled_since = None;
+            } else if settled_since.get_or_insert_with(Instant::now).elapsed() >= SETTLE_TIME {
+                return Ok(());
+            }
+
+            yield_now().await;
+        }
+    }
+
+    /// The pose passed to the last `reset_pose`, or the origin if it was never called
+    pub fn start_pose(&self) -> Vector3<f32> {
+        self.start_pose
+    }
+
+    /// Drives back to the pose odometry was last reset to, for recovering when an auto goes
+    /// wrong in practice. Drop the future to cancel it.
+    pub async fn return_to_start(&mut self) -> anyhow::Result<()> {
+        self.drive_to_pose(self.start_pose, RETURN_TO_START_TOLERANCE)
+            .await
+    }
+
+    pub fn set_balance_gains(&mut self, gains: BalanceGains) {
+        self.balance_gains = gains;
+    }
+
+    /// Radians of tilt on both axes within which `balance` counts the robot as level
+    pub fn set_balance_tolerance(&mut self, tolerance: f32) {
+        self.balance_tolerance = tolerance;
+    }
+
+    /// Balances on a tilting platform like the charge station: drives slowly toward whichever side
+    /// is raised, which tips the platform back toward level, and holds the X-stance brake while
+    /// level. Holds the starting heading and runs until cancelled.
+    pub async fn balance(&mut self) -> anyhow::Result<()> {
+        let heading = self.get_heading();
+        let gains = self.balance_gains;
+
+        loop {
+            let tilt = Vector2::new(self.get_pitch(), self.get_roll());
+
+            if tilt.x.abs() <= self.balance_tolerance && tilt.y.abs() <= self.balance_tolerance {
+                self.heading_setpoint = None;
+                self.brake()?;
+            } else {
+                let velocity = self.robot_to_field((tilt * gains.p).cap_magnitude(gains.max_speed));
+
+                self.heading_setpoint = Some(heading);
+                let omega = self.heading_hold_output(heading);
+
+                self.set_chassis_speeds(ChassisSpeeds::new(velocity.x, velocity.y, omega), true)?;
+            }
+
+            yield_now().await;
+        }
+    }
+
+    /// Holds a normalized teleop style command for `duration`, then brakes. The crudest possible
+    /// auto, for when odometry can't be trusted. The brake also runs if the future is dropped
+    /// early, so the robot never keeps driving on the last command.
+    pub async fn drive_time(
+        &mut self,
+        drive: Vector2<f32>,
+        turn_rate: f32,
+        duration: Duration,
+    ) -> anyhow::Result<()> {
+        let mut drivetrain = BrakeOnDrop(self);
+        let start = Instant::now();
+
+        while start.elapsed() < duration {
+            drivetrain.set_input(drive, turn_rate)?;
+
+            yield_now().await;
+        }
+
+        Ok(())
+    }
+
+    /// Slowly ramps every turn motor's voltage with the drive motors stopped, logging the average
+    /// steering speed at each step, then fits the steering feedforward to the samples. Put the
+    /// robot on blocks or make sure the wheels can turn freely. The ramp ends early if steering
+    /// passes a safety speed cap, and the modules brake when it finishes or the future is
+    /// dropped.
+    pub async fn characterize_turn(&mut self) -> anyhow::Result<TurnCharacterization> {
+        self.ensure_not_emergency_stopped()?;
+
+        let mut drivetrain = BrakeOnDrop(self);
+        let start = Instant::now();
+        let mut samples = Vec::new();
+
+        loop {
+            let volts = start.elapsed().as_secs_f32() * TURN_CHARACTERIZATION_RAMP_RATE;
+            if volts > TURN_CHARACTERIZATION_MAX_VOLTAGE {
+                break;
+            }
+
+            let mut velocity = 0.0;
+            for module in &mut drivetrain.modules {
+                module.set_turn_voltage(volts)?;
+                velocity += module.get_turn_velocity()?.abs() / 4.0;
+            }
+
+            info!("Turn characterization: {volts:.3}V, {velocity:.3}rad/s");
+
+            if velocity > TURN_CHARACTERIZATION_MAX_SPEED {
+                warn!("Steering reached the speed cap, ending turn characterization early");
+                break;
+            }
+
+            if velocity > TURN_CHARACTERIZATION_MIN_SPEED {
+                samples.push((velocity, volts));
+            }
+
+            yield_now().await;
+        }
+
+        drop(drivetrain);
+
+        let n = samples.len() as f32;
+        let (sum_v, sum_volts, sum_vv, sum_v_volts) = samples
+            .iter()
+            .fold((0.0, 0.0, 0.0, 0.0), |(sv, sy, svv, svy), (v, volts)| {
+                (sv + v, sy + volts, svv + v * v, svy + v * volts)
+            });
+        let denominator = n * sum_vv - sum_v * sum_v;
+
+        ensure!(
+            samples.len() >= 2 && denominator > 0.0,
+            "Steering didn't move enough to characterize, got {} moving samples",
+            samples.len()
+        );
+
+        let kv = (n * sum_v_volts - sum_v * sum_volts) / denominator;
+        let ks = (sum_volts - kv * sum_v) / n;
+
+        info!("Turn characterization: ks = {ks:.4}V, kv = {kv:.4}V/(rad/s)");
+
+        Ok(TurnCharacterization { ks, kv })
+    }
+
+    /// Moves the robot a few centimeters by a field relative offset (meters) from the current
+    /// pose, holding heading, then stops. Unlike `drive_relative` this creeps at a very low speed
+    /// and settles to within a centimeter, for final alignment. Errors if it hasn't settled within
+    /// two seconds, e.g. when pushing against something.
+    pub async fn nudge(&mut self, field_offset: Vector2<f32>) -> anyhow::Result<()> {
+        let start = self.get_pose();
+        let target = start.xy() + field_offset;
+        let started = Instant::now();
+
+        self.heading_setpoint = Some(start.z);
+        self.holonomic_controller.reset();
+
+        loop {
+            self.ensure_odometry_fresh()?;
+
+            let error = target - self.get_pose().xy();
+
+            if error.norm() <= NUDGE_TOLERANCE {
+                break;
+            }
+
+            if started.elapsed() > NUDGE_TIMEOUT {
+                self.heading_setpoint = None;
+                self.set_chassis_speeds(ChassisSpeeds::default(), false)?;
+
+                bail!("Nudge did not settle, {:.3}m from the target", error.norm());
+            }
+
+            let speeds = self.holonomic_controller.calculate(
+                self.get_pose(),
+                Vector3::new(target.x, target.y, start.z),
+                Vector3::zeros(),
+                self.loop_period().as_secs_f32(),
+            );
+            let velocity = speeds.velocity().cap_magnitude(NUDGE_MAX_SPEED);
+            let max_omega = self.max_heading_output();
+            let omega = speeds.omega.clamp(-max_omega, max_omega);
+
+            self.set_chassis_speeds(ChassisSpeeds::new(velocity.x, velocity.y, omega), false)?;
+
+            yield_now().await;
+        }
+
+        self.heading_setpoint = None;
+        self.set_chassis_speeds(ChassisSpeeds::default(), false)
+    }
+
+    /// Drives a robot relative displacement (meters, x forward) measured from the pose when
+    /// called, holding the starting heading
+    pub async fn drive_relative(
+        &mut self,
+        displacement: Vector2<f32>,
+        tolerance: f32,
+    ) -> anyhow::Result<()> {
+        let start = self.get_pose();
+        let target = start.xy() + Rotation2::new(start.z) * displacement;
+
+        self.drive_to_pose(Vector3::new(target.x, target.y, start.z), tolerance)
+            .await
+    }
+
+    /// Feeds a recorded input trace through `set_input` at its recorded timestamps, then brakes
+    pub async fn replay_inputs(&mut self, trace: &InputTrace) -> anyhow::Result<()> {
+        let start = Instant::now();
+
+        loop {
+            let time = start.elapsed().as_secs_f32();
+            if time > trace.duration() {
+                break;
+            }
+
+            if let Some(sample) = trace.sample(time) {
+                self.set_input(sample.drive, sample.turn_rate)?;
+            }
+
+            yield_now().await;
+        }
+
+        self.brake()
+    }
+
+    /// Blends a driver command with an automated assist command before slew limiting
+    pub fn set_input_blended(
+        &mut self,
+        driver: DriveCommand,
+        assist: DriveCommand,
+        weights: BlendWeights,
+    ) -> anyhow::Result<()> {
+        let command = driver.blend(assist, weights);
+
+        self.set_input(command.drive, command.turn_rate)
+    }
+
+    /// Measured drive velocities in meters per second, in module order, signed relative to the
+    /// requested direction of each module so they can be compared directly
+    pub fn get_module_drive_velocities(&mut self) -> anyhow::Result<[f32; 4]> {
+        let mut velocities = [0.0; 4];
+
+        for (velocity, module) in velocities.iter_mut().zip(&mut self.modules) {
+            *velocity = module.get_drive_velocity()?;
+        }
+
+        Ok(velocities)
+    }
+
+    pub fn set_stall_thresholds(&mut self, thresholds: StallThresholds) {
+        self.stall_thresholds = thresholds;
+    }
+
+    /// When enabled, staying stalled for `delay` cuts the translation command down until the
+    /// robot moves again or the command drops, so holding against a wall doesn't cook the motors
+    pub fn set_stall_protection(&mut self, enabled: bool, delay: Duration) {
+        self.stall_protection = enabled.then_some(delay);
+        self.stalled_since = None;
+    }
+
+    /// Whether translation is being commanded while the drive motors draw high current and the
+    /// wheels barely turn, e.g. pinned against a wall. Counts as not stalled if a module can't be
+    /// read.
+    pub fn is_stalled(&mut self) -> bool {
+        let thresholds = self.stall_thresholds;
+
+        if self.commanded_speeds.velocity().norm() < thresholds.command {
+            return false;
+        }
+
+        let mut current = 0.0;
+        let mut velocity = 0.0;
+
+        for module in &mut self.modules {
+            let (Ok(module_current), Ok(module_velocity)) =
+                (module.get_drive_current(), module.get_drive_velocity())
+            else {
+                return false;
+            };
+
+            current += module_current / 4.0;
+            velocity += module_velocity.abs() / 4.0;
+        }
+
+        current >= thresholds.current && velocity < thresholds.velocity
+    }
+
+    fn stall_protected(&mut self) -> bool {
+        let Some(delay) = self.stall_protection else {
+            return false;
+        };
+
+        if !self.is_stalled() {
+            self.stalled_since = None;
+            return false;
+        }
+
+        self.stalled_since
+            .get_or_insert_with(Instant::now)
+            .elapsed()
+            >= delay
+    }
+
+    /// Drive and turn applied outputs as a fraction of bus voltage, in module order. See
+    /// `SwerveModule::applied_outputs`.
+    pub fn get_module_applied_outputs(&mut self) -> anyhow::Result<[(f32, f32); 4]> {
+        let mut outputs = [(0.0, 0.0); 4];
+
+        for (output, module) in outputs.iter_mut().zip(&mut self.modules) {
+            *output = module.applied_outputs()?;
+        }
+
+        Ok(outputs)
+    }
+
+    /// Commanded minus measured drive velocity in meters per second, in module order. Both sides
+    /// are taken after optimize, so a reversed wheel compares like for like. Logged over time
+    /// this shows which module's drive loop is mistuned.
+    pub fn drive_velocity_errors(&mut self) -> anyhow::Result<[f32; 4]> {
+        let mut errors = [0.0; 4];
+
+        for (error, module) in errors.iter_mut().zip(&mut self.modules) {
+            *error = module.target_state().get_drive() - module.get_state()?.get_drive();
+        }
+
+        Ok(errors)
+    }
+
+    /// Bench testing mode: spawns a task that drives the field relative `vx`, `vy` (m/s) and
+    /// `omega` (rad/s) entries of `table` through `set_chassis_speeds` every loop, with missing
+    /// entries read as zero. Driver input through `set_input` or `set_input_with_frames` ends
+    /// the mode for good, so it never fights the driver.
+    pub fn enable_nt_command_mode(
+        drivetrain: &'static Subsystem<Drivetrain>,
+        table: Rc<dyn NetworkTable>,
+    ) {
+        // Locked at teleop's priority so neither cancels the other
+        spawn(async move {
+            drivetrain.lock(1).await.nt_command_mode = true;
+            info!("Entering NetworkTables command mode");
+
+            loop {
+                let mut drivetrain = drivetrain.lock(1).await;
+
+                if !drivetrain.nt_command_mode {
+                    break;
+                }
+
+                if let Err(err) = drivetrain.apply_nt_command(&*table) {
+                    warn!("Failed to apply the NetworkTables command: {err:#}");
+                }
+
+                drop(drivetrain);
+                yield_now().await;
+            }
+        })
+        .detach();
+    }
+
+    pub fn is_nt_command_mode(&self) -> bool {
+        self.nt_command_mode
+    }
+
+    /// One loop of the NetworkTables command task
+    fn apply_nt_command(&mut self, table: &dyn NetworkTable) -> anyhow::Result<()> {
+        let read = |key| table.get_f64(key).unwrap_or(0.0) as f32;
+        let speeds = ChassisSpeeds::new(read("vx"), read("vy"), read("omega"));
+
+        ensure!(
+            [speeds.vx, speeds.vy, speeds.omega]
+                .iter()
+                .all(|value| value.is_finite()),
+            "Commanded speeds must be finite, got {speeds:?}"
+        );
+
+        self.set_chassis_speeds(speeds, true)
+    }
+
+    /// Sets the table and key the pose is published to, or stops publishing. The odometry task
+    /// publishes every loop whether or not the robot is being driven.
+    pub fn set_pose_publisher(&mut self, publisher: Option<(Rc<dyn NetworkTable>, String)>) {
+        *self.pose_publisher.borrow_mut() = publisher;
+    }
+
+    /// Publishes the pose now in the `Field2d` format, `[x, y, heading]` in meters and degrees
+    /// counterclockwise positive, e.g. from a simulation loop with no odometry task. A failed
+    /// publish is logged rather than returned, so telemetry can never stop the robot.
+    pub fn publish_pose(&self) {
+        if let Err(err) = publish_field2d(&self.pose_publisher.borrow(), self.get_pose()) {
+            warn!("Failed to publish the pose: {err:#}");
+        }
+    }
+
+    fn record_pose(&mut self) {
+        if self.pose_history.len() == POSE_HISTORY_LEN {
+            self.pose_history.pop_front();
+        }
+
+        self.pose_history
+            .push_back((Instant::now(), self.get_pose()));
+    }
+
+    /// The pose history as CSV with a `time,x,y,heading` header, in seconds since the oldest
+    /// sample, meters and radians. Holds at most the last 500 drive commands, 10 seconds at the
+    /// nominal loop rate.
+    pub fn get_pose_history_csv(&self) -> String {
+        let mut csv = String::from("time,x,y,heading\n");
+
+        if let Some((start, _)) = self.pose_history.front() {
+            for (time, pose) in &self.pose_history {
+                let _ = writeln!(
+                    csv,
+                    "{:.3},{:.4},{:.4},{:.4}",
+                    (*time - *start).as_secs_f32(),
+                    pose.x,
+                    pose.y,
+                    pose.z
+                );
+            }
+        }
+
+        csv
+    }
+
+    /// Starts writing the pose, module states and drive currents to a WPILib `.wpilog` file at
+    /// `path` every loop, for analysis in AdvantageScope or the DataLogTool. The pose history CSV
+    /// stays available alongside it. Module states are `[angle, speed]` pairs in module order.
+    pub fn start_datalog(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut log = DataLog::create(path)?;
+
+        self.datalog = Some(DrivetrainLog {
+            pose: log.start_entry("/drivetrain/pose", "double[]")?,
+            module_states: log.start_entry("/drivetrain/module_states", "double[]")?,
+            currents: log.start_entry("/drivetrain/currents", "double[]")?,
+            log,
+        });
+
+        Ok(())
+    }
+
+    /// Flushes and closes the DataLog, if one is open
+    pub fn stop_datalog(&mut self) -> anyhow::Result<()> {
+        if let Some(mut datalog) = self.datalog.take() {
+            datalog.log.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends one sample to the DataLog. A failed write is logged and closes the log rather
+    /// than failing the drive command.
+    fn write_datalog(&mut self) {
+        if self.datalog.is_none() {
+            return;
+        }
+
+        // Only the logged signals, since every CAN read here happens on every drive command
+        let pose = self.get_pose().map(f64::from);
+        let mut module_states = Vec::with_capacity(8);
+        let mut currents = [0.0; 4];
+
+        for (module, current) in self.modules.iter_mut().zip(&mut currents) {
+            let state = module.get_state().unwrap_or(SwerveState::new(0.0, 0.0));
+            module_states.extend([state.get_angle() as f64, state.get_drive() as f64]);
+            *current = module.get_drive_current().unwrap_or(0.0) as f64;
+        }
+
+        let Some(datalog) = &mut self.datalog else {
+            return;
+        };
+
+        let result = datalog
+            .log
+            .append_double_array(datalog.pose, pose.as_slice())
+            .and_then(|_| {
+                datalog
+                    .log
+                    .append_double_array(datalog.module_states, &module_states)
+            })
+            .and_then(|_| datalog.log.append_double_array(datalog.currents, &currents));
+
+        if let Err(err) = result {
+            error!("Failed to write the DataLog, closing it: {err:#}");
+            self.datalog = None;
+        }
+    }
+
+    /// Lowers or raises the drive motor current limit (amps) on every module, e.g. to avoid
+    /// brownouts late in a match
+    pub fn set_drive_current_limit(&mut self, amps: u8) -> anyhow::Result<()> {
+        for module in &mut self.modules {
+            module.set_drive_current_limit(amps)?;
+        }
+
+        Ok(())
+    }
+
+    /// Turn motor equivalent of `set_drive_current_limit`
+    pub fn set_turn_current_limit(&mut self, amps: u8) -> anyhow::Result<()> {
+        for module in &mut self.modules {
+            module.set_turn_current_limit(amps)?;
+        }
+
+        Ok(())
+    }
+
+    /// Measured module angles in degrees within `[0, 360)`, in module order
+    pub fn get_module_angles_degrees(&mut self) -> anyhow::Result<[f32; 4]> {
+        let mut angles = [0.0; 4];
+
+        for (angle, module) in angles.iter_mut().zip(&mut self.modules) {
+            *angle = module.get_angle()?.to_degrees().rem_euclid(360.0);
+        }
+
+        Ok(angles)
+    }
+
+    /// Commanded module angles in degrees within `[0, 360)`, comparable with
+    /// `get_module_angles_degrees`
+    pub fn get_module_target_angles_degrees(&self) -> [f32; 4] {
+        self.modules.each_ref().map(|module| {
+            module
+                .target_state()
+                .get_angle()
+                .to_degrees()
+                .rem_euclid(360.0)
+        })
+    }
+
+    /// Gathers every drivetrain reading in a single pass
+    pub fn snapshot(&mut self) -> DrivetrainSnapshot {
+        let heading = self.get_heading();
+
+        let mut module_states = [None; 4];
+        let mut module_positions = [None; 4];
+        let mut currents = [None; 4];
+
+        let mut drive_velocity_errors = [None; 4];
+        let mut applied_outputs = [None; 4];
+
+        for (i, module) in self.modules.iter_mut().enumerate() {
+            module_states[i] = module.get_state().ok();
+            module_positions[i] = module.get_position().ok();
+            currents[i] = module.get_drive_current().ok();
+            drive_velocity_errors[i] =
+                module_states[i].map(|state| module.target_state().get_drive() - state.get_drive());
+            applied_outputs[i] = module.applied_outputs().ok();
+        }
+
+        let velocity = match module_states {
+            [Some(front_left), Some(front_right), Some(rear_left), Some(rear_right)] => {
+                let speeds =
+                    self.kinematics
+                        .forward([front_left, front_right, rear_left, rear_right]);
+
+                Some(self.robot_to_field(speeds.xy()))
+            }
+            _ => None,
+        };
+
+        DrivetrainSnapshot {
+            pose: self.get_pose(),
+            heading,
+            velocity,
+            module_states,
+            module_positions,
+            currents,
+            drive_velocity_errors,
+            applied_outputs,
+            gyro_rate: self.get_turn_rate(),
+            odometry_rate: self.odometry_rate(),
+        }
+    }
+
+    /// Tests one module at a time: steps the steering slowly through a full revolution checking
+    /// it reaches each angle, then drives briefly checking the wheel moves forward about as far
+    /// as commanded, reporting it as inverted if it turned backward instead. Stops at the first
+    /// failure, leaving the remaining modules skipped.
+    pub async fn run_self_test(&mut self) -> SelfTestReport {
+        let mut modules = [
+            ModuleTestResult::Skipped,
+            ModuleTestResult::Skipped,
+            ModuleTestResult::Skipped,
+            ModuleTestResult::Skipped,
+        ];
+
+        self.stop();
+
+        if let Err(err) = self.ensure_not_emergency_stopped() {
+            warn!("Self test skipped: {err:#}");
+            return SelfTestReport { modules };
+        }
+
+        for index in 0..self.modules.len() {
+            let result = self
+                .self_test_module(index)
+                .await
+                .unwrap_or_else(|err| ModuleTestResult::Failed(format!("{err:#}")));
+
+            self.modules[index].stop();
+
+            let failed = result != ModuleTestResult::Passed;
+            info!("Self test {} module: {result}", MODULE_NAMES[index]);
+            modules[index] = result;
+
+            if failed {
+                warn!("Self test stopped at the {} module", MODULE_NAMES[index]);
+                break;
+            }
+        }
+
+        SelfTestReport { modules }
+    }
+
+    /// Returns the reason the module failed, if it did
+    async fn self_test_module(&mut self, index: usize) -> anyhow::Result<ModuleTestResult> {
+        let start = self.modules[index].get_angle()?;
+
+        for step in 1..=SELF_TEST_STEPS {
+            let target = start + TAU * step as f32 / SELF_TEST_STEPS as f32;
+
+            self.modules[index].set_target_raw(SwerveState::new(target, 0.0))?;
+            delay(SELF_TEST_STEP_TIME).await;
+
+            let error = wrap_angle(self.modules[index].get_angle()? - target);
+            if error.abs() > SELF_TEST_ANGLE_TOLERANCE {
+                return Ok(ModuleTestResult::Failed(format!(
+                    "steering missed {:.0} degrees by {:.0} degrees",
+                    target.to_degrees(),
+                    error.to_degrees()
+                )));
+            }
+        }
+
+        let start_position = self.modules[index].get_position()?.drive;
+
+        self.modules[index].set_target_raw(SwerveState::new(start, SELF_TEST_DRIVE_SPEED))?;
+        delay(SELF_TEST_DRIVE_TIME / 2).await;
+        let velocity = self.modules[index].get_drive_velocity()?;
+        delay(SELF_TEST_DRIVE_TIME / 2).await;
+        self.modules[index].set_target_raw(SwerveState::new(start, 0.0))?;
+
+        let distance = self.modules[index].get_position()?.drive - start_position;
+        let expected = SELF_TEST_DRIVE_SPEED * SELF_TEST_DRIVE_TIME.as_secs_f32();
+
+        // Driving backward when commanded forward means a sign is flipped somewhere
+        if velocity < 0.0 && distance < 0.0 {
+            return Ok(ModuleTestResult::Inverted(velocity));
+        }
+
+        if distance < expected * SELF_TEST_MIN_DISTANCE_RATIO {
+            return Ok(ModuleTestResult::Failed(format!(
+                "drove {distance:.3}m, expected about {expected:.3}m"
+            )));
+        }
+
+        Ok(ModuleTestResult::Passed)
+    }
+
+    /// Arms or disarms the turn wrapping check. On by default; turn it off for bench testing
+    /// where the modules can't move.
+    pub fn set_verify_turn_wrapping(&mut self, enabled: bool) {
+        self.verify_turn_wrapping = enabled;
+    }
+
+    /// Checks every turn motor crosses the absolute encoder's 0/2PI seam the short way. If the
+    /// PID wrapping range disagrees with the encoder conversion factor, modules silently steer
+    /// the long way around, so this steers each one just either side of the seam and fails if
+    /// any reading lands on the far side of the circle. It disarms itself once it passes, so it
+    /// only runs until the first success, and does nothing if turned off with
+    /// `set_verify_turn_wrapping`. It needs the robot enabled since it moves the modules.
+    pub async fn check_turn_wrapping(&mut self) -> anyhow::Result<()> {
+        if !self.verify_turn_wrapping {
+            return Ok(());
+        }
+
+        self.ensure_not_emergency_stopped()?;
+
+        for index in 0..self.modules.len() {
+            let offset = self.modules[index].get_offset();
+
+            // Angles here are relative to the offset, so subtract it to land on the raw seam
+            self.modules[index]
+                .set_target_raw(SwerveState::new(-WRAP_CHECK_ANGLE - offset, 0.0))?;
+            delay(WRAP_CHECK_STEP_TIME).await;
+
+            self.modules[index].set_target_raw(SwerveState::new(WRAP_CHECK_ANGLE - offset, 0.0))?;
+            let start = Instant::now();
+
+            while start.elapsed() < WRAP_CHECK_STEP_TIME {
+                let raw = wrap_angle(self.modules[index].get_raw_angle()?);
+
+                if raw.abs() > FRAC_PI_2 {
+                    self.modules[index].stop();
+
+                    bail!(
+                        "The {} module steered the long way around the encoder seam, check the \
+                         turn encoder conversion factor, inversion and PID wrapping range",
+                        MODULE_NAMES[index]
+                    );
+                }
+
+                yield_now().await;
+            }
+
+            self.modules[index].hold()?;
+        }
+
+        self.verify_turn_wrapping = false;
+        info!("Turn wrapping check passed");
+
+        Ok(())
+    }
+
+    /// Commands each module directly in module order, bypassing kinematics and everything in the
+    /// chassis speed path. The steer deadband, optimize and offsets still apply. Ignored while
+    /// disabled or emergency stopped, like any other drive command.
+    pub fn set_modules(&mut self, states: [SwerveState; 4]) -> anyhow::Result<()> {
+        if !self.enabled || self.emergency_stopped {
+            return Ok(());
+        }
+
+        self.ensure_velocity_control()?;
+
+        for (module, state) in self.modules.iter_mut().zip(states) {
+            module.set_target(state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Points every module at the same robot relative angle (radians) with zero drive, skipping
+    /// optimize so each wheel physically faces that angle, e.g. for lining up against a jig
+    pub fn point_all(&mut self, angle: f32) -> anyhow::Result<()> {
+        self.ensure_not_emergency_stopped()?;
+
+        for module in &mut self.modules {
+            module.set_target_raw(SwerveState::new(angle, 0.0))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the current pose to `path`, e.g. `POSE_PATH`, so `load_pose` can restore it after
+    /// a code restart
+    pub fn save_pose(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        save_pose(path, self.get_pose())
+    }
+
+    /// Resets odometry to the pose saved at `path` and returns it. A missing or unreadable file
+    /// is logged and resets to the origin instead.
+    pub fn load_pose(&mut self, path: impl AsRef<Path>) -> Vector3<f32> {
+        let pose = match load_pose(path) {
+            Ok(Some(pose)) => pose,
+            Ok(None) => {
+                warn!("No saved pose, starting at the origin");
+                Vector3::zeros()
+            }
+            Err(err) => {
+                warn!("Failed to load the saved pose, starting at the origin: {err:#}");
+                Vector3::zeros()
+            }
+        };
+
+        self.reset_pose(pose);
+
+        pose
+    }
+
+    /// Writes the active module offsets to `path` in the format loaded at startup, and checks the
+    /// file reads back to the same values
+    pub fn save_offsets(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        save_offsets(path, self.get_module_offsets())
+    }
+
+    /// The active module offsets in radians, normalized, in module order: front left, front
+    /// right, rear left, rear right. The same layout `save_offsets` writes.
+    pub fn get_module_offsets(&self) -> [f32; 4] {
+        self.modules.each_ref().map(SwerveModule::get_offset)
+    }
+
+    /// Replaces one module's offset in radians for live tuning, e.g. from a dashboard, without
+    /// touching the saved file. Modules are indexed in the order of `get_module_offsets`; persist
+    /// a good value with `save_offsets`.
+    pub fn set_offset(&mut self, index: usize, offset: f32) -> anyhow::Result<()> {
+        ensure!(
+            index < self.modules.len(),
+            "Module index {index} is out of range, expected 0..{}",
+            self.modules.len()
+        );
+        ensure!(offset.is_finite(), "Module offsets must be finite");
+        // Applying an offset steers the module to keep its target
+        self.ensure_not_emergency_stopped()?;
+
+        self.modules[index].set_offset(offset)
+    }
+
+    /// Measured angle and drive distance (meters) of each module, in module order
+    pub fn get_module_positions(&mut self) -> anyhow::Result<[SwerveState; 4]> {
+        let mut positions = [SwerveState::new(0.0, 0.0); 4];
+
+        for (position, module) in positions.iter_mut().zip(&mut self.modules) {
+            *position = module.get_position()?;
+        }
+
+        Ok(positions)
+    }
+
+    /// Moves one module's reported drive distance to `meters`, e.g. to continue a measurement
+    /// from a nonzero baseline. Odometry is unaffected.
+    pub fn set_drive_position(&mut self, index: usize, meters: f32) -> anyhow::Result<()> {
+        ensure!(
+            index < self.modules.len(),
+            "Module index {index} is out of range, expected 0..{}",
+            self.modules.len()
+        );
+
+        self.modules[index].set_drive_position(meters)
+    }
+
+    /// Takes the current wheel angles as straight ahead and applies them as the new module
+    /// offsets. Point every wheel forward, bevel gears facing the same way, before calling this.
+    /// With `save` the offsets are also written to `OFFSETS_PATH` so they are loaded on the next
+    /// boot; a failed save is logged and the new offsets stay active until then.
+    pub fn calibrate_offsets(&mut self, save: bool) -> anyhow::Result<[f32; 4]> {
+        self.ensure_not_emergency_stopped()?;
+
+        let mut offsets = [0.0; 4];
+        for (offset, module) in offsets.iter_mut().zip(&mut self.modules) {
+            *offset = module.get_raw_angle()?;
+        }
+
+        for (module, offset) in self.modules.iter_mut().zip(offsets) {
+            module.set_offset(offset)?;
+        }
+
+        let offsets = self.get_module_offsets();
+        info!("Calibrated module offsets: {offsets:?}");
+
+        if save {
+            if let Err(err) = self.save_offsets(OFFSETS_PATH) {
+                error!("Failed to save module offsets: {err:#}");
+            }
+        }
+
+        Ok(offsets)
+    }
+
+    /// Switches every module's drive motor between velocity and current control. In current
+    /// mode the drive values sent to the modules are amps rather than meters per second, so the
+    /// normal input path and `set_modules` refuse to drive; drive the modules directly with
+    /// `set_module_drive_currents` instead. Read the safety notes on `DriveControlMode::Current`
+    /// first.
+    pub fn set_drive_control_mode(&mut self, mode: DriveControlMode) -> anyhow::Result<()> {
+        for module in &mut self.modules {
+            module.set_drive_control_mode(mode)?;
+        }
+
+        Ok(())
+    }
+
+    /// Errors if any module is in current control, where velocity commands would be read as amps
+    fn ensure_velocity_control(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.modules
+                .iter()
+                .all(|module| module.drive_control_mode() == DriveControlMode::Velocity),
+            "Drive commands are ignored in current control mode, use set_module_drive_currents"
+        );
+
+        Ok(())
+    }
+
+    /// Steers each module to its angle (radians) and drives it with its current (amps), in module
+    /// order. Requires current control mode, and errors while emergency stopped.
+    pub fn set_module_drive_currents(&mut self, states: [SwerveState; 4]) -> anyhow::Result<()> {
+        self.ensure_not_emergency_stopped()?;
+        ensure!(
+            self.modules
+                .iter()
+                .all(|module| module.drive_control_mode() == DriveControlMode::Current),
+            "Drive currents can only be commanded in current control mode"
+        );
+
+        for (module, state) in self.modules.iter_mut().zip(states) {
+            module.set_target_steered(state)?;
+        }
+
+        Ok(())
+    }
+
+    /// When enabled, modules held still for `delay` relax their turn motors until the next
+    /// command instead of buzzing against the steering PID
+    pub fn set_turn_idle_relax(&mut self, enabled: bool, delay: Duration) {
+        for module in &mut self.modules {
+            module.set_turn_idle_relax(enabled, delay);
+        }
+    }
+
+    /// Sets the time between cutting each module in `stop_staggered`
+    pub fn set_stop_stagger(&mut self, interval: Duration) {
+        self.stop_stagger = interval;
+    }
+
+    /// Stops the modules one at a time, spreading the current transient of cutting all four
+    /// drive motors over a few milliseconds. Use `stop` when the robot has to stop immediately.
+    /// While emergency stopped every module stops at once.
+    pub async fn stop_staggered(&mut self) {
+        if self.emergency_stopped {
+            self.stop();
+            return;
+        }
+
+        for index in 0..self.modules.len() {
+            if index > 0 {
+                delay(self.stop_stagger).await;
+            }
+
+            self.modules[index].stop();
+        }
+    }
+
+    pub fn brake(&mut self) -> anyhow::Result<()> {
+        if self.emergency_stopped {
+            return Ok(());
+        }
+
+        for (module, state) in self
+            .modules
+            .iter_mut()
+            .zip(self.kinematics.brake().into_iter())
+        {
+            module.set_target_steered(state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Holds the X-stance brake and the heading it started at, for defensive stands against
+    /// robots trying to spin us. The X wheels point at the center of the robot, so they can't
+    /// push back against rotation; once pushed more than a small tolerance off heading the
+    /// modules leave the X, the heading controller turns back, and the X is restored. Runs until
+    /// dropped.
+    pub async fn brake_hold_heading(&mut self) -> anyhow::Result<()> {
+        let heading = self.get_heading();
+
+        loop {
+            if self.heading_error_to(heading).abs() > BRAKE_HOLD_HEADING_TOLERANCE {
+                self.heading_setpoint = Some(heading);
+                let omega = self.heading_hold_output(heading);
+
+                self.set_chassis_speeds(ChassisSpeeds::new(0.0, 0.0, omega), false)?;
+            } else {
+                self.heading_setpoint = None;
+                self.brake()?;
+            }
+
+            yield_now().await;
+        }
+    }
+}
+
+/// A DataLog with the drivetrain's entries registered
+struct DrivetrainLog {
+    log: DataLog,
+    pose: u32,
+    module_states: u32,
+    currents: u32,
+}
+
+/// Borrows the drivetrain and puts it in the X-stance brake when dropped
+struct BrakeOnDrop<'a>(&'a mut Drivetrain);
+
+impl Deref for BrakeOnDrop<'_> {
+    type Target = Drivetrain;
+
+    fn deref(&self) -> &Drivetrain {
+        self.0
+    }
+}
+
+impl DerefMut for BrakeOnDrop<'_> {
+    fn deref_mut(&mut self) -> &mut Drivetrain {
+        self.0
+    }
+}
+
+impl Drop for BrakeOnDrop<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.0.brake() {
+            warn!("Failed to brake: {err:#}");
+        }
+    }
+}
+
+/// The gyro's yaw converted from its clockwise positive degrees to counterclockwise positive
+/// radians. Everything past this boundary works in radians.
+fn gyro_yaw(gyro: &dyn Gyro) -> Radians {
+    -Radians::from(Degrees(gyro.heading()))
+}
+
+/// Publishes a pose to the pose publisher, if one is set, in the `Field2d` format
+fn publish_field2d(
+    publisher: &Option<(Rc<dyn NetworkTable>, String)>,
+    pose: Vector3<f32>,
+) -> anyhow::Result<()> {
+    let Some((table, key)) = publisher else {
+        return Ok(());
+    };
+
+    table.set_f64_array(
+        key,
+        &[
+            pose.x as f64,
+            pose.y as f64,
+            normalize_angle(pose.z).to_degrees() as f64,
+        ],
+    )
+}
+
+/// One odometry step, shared by `Drivetrain::update_odometry` and the background odometry task
+fn integrate_odometry(
+    odometry: &Odometry<SwerveKinematics>,
+    last_update: &Cell<Instant>,
+    states: [SwerveState; 4],
+    heading: f32,
+) {
+    odometry.update(states, heading);
+    last_update.set(Instant::now());
+}
+
+/// `ChassisSpeeds::from_arc`, slowed until both the speed and the turn rate are within the speed
+/// limits
+fn arc_speeds(radius: f32, speed: f32) -> ChassisSpeeds {
+    let max_speed = MAX_VELOCITY_LIMIT.min(MAX_ROTATION_LIMIT * radius.abs());
+
+    ChassisSpeeds::from_arc(radius, speed.clamp(-max_speed, max_speed))
+}
+
+/// Module positions in meters from the robot center (x forward, y left) in module order, for a
+/// tapered chassis whose front and rear modules are different distances apart
+pub fn module_positions_from_widths(
+    front_track_width: f32,
+    rear_track_width: f32,
+    wheel_base: f32,
+) -> [Vector2<f32>; 4] {
+    [
+        Vector2::new(wheel_base / 2.0, front_track_width / 2.0),
+        Vector2::new(wheel_base / 2.0, -front_track_width / 2.0),
+        Vector2::new(-wheel_base / 2.0, rear_track_width / 2.0),
+        Vector2::new(-wheel_base / 2.0, -rear_track_width / 2.0),
+    ]
+}
+
+/// Field heading in radians, counterclockwise positive
+fn gyro_heading(gyro: &dyn Gyro, offset: f32) -> f32 {
+    offset + gyro_yaw(gyro).0
+}
+
+impl Drivetrain {
+    /// Builds the drivetrain around any gyro, e.g. a `FakeGyro` when running without a NavX
+    pub fn with_gyro(gyro: impl Gyro + 'static) -> anyhow::Result<Self> {
+        Self::with_module_positions(
+            gyro,
+            module_positions_from_dimensions(TRACK_WIDTH, WHEEL_BASE),
+        )
+    }
+
+    /// Like `with_gyro`, but with explicit module positions in meters from the robot center
+    /// (x forward, y left) in module order, for chassis that aren't a symmetric rectangle. See
+    /// `module_positions_from_widths`.
+    pub fn with_module_positions(
+        gyro: impl Gyro + 'static,
+        module_positions: [Vector2<f32>; 4],
+    ) -> anyhow::Result<Self> {
+        let offsets = match load_offsets(OFFSETS_PATH) {
+            Ok(offsets) => offsets.unwrap_or(DEFAULT_OFFSETS),
+            Err(err) => {
+                warn!("Failed to load module offsets, using defaults: {err:#}");
+                DEFAULT_OFFSETS
+            }
+        };
+
+        let (front_left, front_left_state) = SwerveModule::new(
+            SparkMaxModule::new(3, 4, MODULE_GAINS[0])?,
+            Rotation2::new(offsets[0]),
+            0.0,
+        )?;
+        let (front_right, front_right_state) = SwerveModule::new(
+            SparkMaxModule::new(1, 2, MODULE_GAINS[1])?,
+            Rotation2::new(offsets[1]),
+            0.0,
+        )?;
+        let (rear_left, rear_left_state) = SwerveModule::new(
+            SparkMaxModule::new(5, 6, MODULE_GAINS[2])?,
+            Rotation2::new(offsets[2]),
+            0.0,
+        )?;
+        let (rear_right, rear_right_state) = SwerveModule::new(
+            SparkMaxModule::new(7, 8, MODULE_GAINS[3])?,
+            Rotation2::new(offsets[3]),
+            0.0,
+        )?;
+
+        let drivetrain = Self::from_modules(
+            gyro,
+            [front_left, front_right, rear_left, rear_right],
+            module_positions,
+        )?;
+
+        drivetrain.spawn_odometry([
+            front_left_state,
+            front_right_state,
+            rear_left_state,
+            rear_right_state,
+        ]);
+
+        Ok(drivetrain)
+    }
+
+    /// Runs odometry in the background from the modules' odometry readers, in module order
+    fn spawn_odometry(
+        &self,
+        mut readers: [impl FnMut() -> anyhow::Result<SwerveState> + 'static; 4],
+    ) {
+        let odometry = self.odometry.clone();
+        let gyro = self.gyro.clone();
+        let heading_offset = self.heading_offset.clone();
+        let last_odometry_update = self.last_odometry_update.clone();
+        let odometry_period = self.odometry_period.clone();
+        let odometry_rate = self.odometry_rate.clone();
+        let pose_publisher = self.pose_publisher.clone();
+
+        spawn(async move {
+            // The gyro heading wraps, so unwrap it before it reaches odometry, which would
+            // otherwise see a full turn whenever the robot crosses the seam
+            let mut heading = AngleUnwrapper::default();
+            let mut last_slow_warning: Option<Instant> = None;
+            let mut last_publish: Option<Instant> = None;
+            let mut publish_failing = false;
+
+            loop {
+                let iteration_start = Instant::now();
+
+                let _ = log(async {
+                    let mut states = [SwerveState::new(0.0, 0.0); 4];
+                    for (state, reader) in states.iter_mut().zip(&mut readers) {
+                        *state = reader()?;
+                    }
+
+                    integrate_odometry(
+                        &odometry,
+                        &last_odometry_update,
+                        states,
+                        heading.update(gyro_heading(&*gyro, heading_offset.get())),
+                    );
+
+                    anyhow::Ok(())
+                })
+                .await;
+
+                if last_publish.map_or(true, |last| last.elapsed() >= POSE_PUBLISH_PERIOD) {
+                    last_publish = Some(Instant::now());
+
+                    // Logged once per run of failures rather than every loop
+                    match publish_field2d(&pose_publisher.borrow(), odometry.get_pose()) {
+                        Ok(()) => publish_failing = false,
+                        Err(err) if !publish_failing => {
+                            warn!("Failed to publish the pose: {err:#}");
+                            publish_failing = true;
+                        }
+                        Err(_) => {}
+                    }
+                }
+
+                let period = odometry_period.get();
+                let remaining = period
+                    .unwrap_or_default()
+                    .saturating_sub(iteration_start.elapsed());
+
+                if remaining.is_zero() {
+                    yield_now().await;
+                } else {
+                    delay(remaining).await;
+                }
+
+                let elapsed = iteration_start.elapsed().as_secs_f32();
+                odometry_rate.set(1.0 / elapsed);
+
+                if let Some(period) = period {
+                    let slow = elapsed > period.as_secs_f32() * ODOMETRY_SLOW_FACTOR;
+                    let warned_recently = last_slow_warning
+                        .is_some_and(|warned| warned.elapsed() < ODOMETRY_SLOW_WARNING_INTERVAL);
+
+                    if slow && !warned_recently {
+                        warn!(
+                            "Odometry is running at {:.0}Hz, slower than the expected {:.0}Hz",
+                            1.0 / elapsed,
+                            1.0 / period.as_secs_f32()
+                        );
+                        last_slow_warning = Some(Instant::now());
+                    }
+                }
+            }
+        })
+        .detach();
+    }
+
+    /// Builds the drivetrain around already constructed modules, in module order, without
+    /// starting the background odometry task. Odometry only moves through `update_odometry`, so
+    /// simulation and tests can step it themselves. See `sim::sim_drivetrain`.
+    pub fn from_modules(
+        gyro: impl Gyro + 'static,
+        modules: [SwerveModule; 4],
+        module_positions: [Vector2<f32>; 4],
+    ) -> anyhow::Result<Self> {
+        ensure!(
+            module_positions
+                .iter()
+                .all(|position| position.x.is_finite() && position.y.is_finite()),
+            "Module positions must be finite"
+        );
+        ensure!(
+            module_positions
+                .iter()
+                .any(|position| position.norm() > 0.0),
+            "Module positions can't all be at the robot center"
+        );
+
+        let kinematics = SwerveKinematics::new(module_positions);
+        let odometry = Odometry::new(kinematics.clone(), Vector3::new(0.0, 0.0, 0.0));
+        let gyro: Rc<dyn Gyro> = Rc::new(gyro);
+
+        let mut x_limit = SlewLimiter::new(MAX_ACCEL);
+        let mut y_limit = SlewLimiter::new(MAX_ACCEL);
+        x_limit.set_jerk_limit(MAX_JERK);
+        y_limit.set_jerk_limit(MAX_JERK);
+
+        Ok(Self {
+            angle_limit: SlewLimiter::new(MAX_ANGLE_ACCEL),
+            x_limit,
+            y_limit,
+            instant_stop_on_release: false,
+
+            loop_period_override: None,
+            measured_loop_period: NOMINAL_LOOP_PERIOD,
+            last_loop: None,
+
+            auto_brake_delay: None,
+            released_since: None,
+
+            stop_stagger: STOP_STAGGER,
+
+            enabled: true,
+            emergency_stopped: false,
+            coast_on_disable: false,
+            verify_turn_wrapping: VERIFY_TURN_WRAPPING,
+            soft_start: None,
+            enabled_at: None,
+
+            balance_gains: BalanceGains::default(),
+            balance_tolerance: BALANCE_TOLERANCE,
+
+            collision_abort: false,
+            collision_threshold: COLLISION_THRESHOLD,
+
+            saturation_policy: SaturationPolicy::default(),
+
+            module_healthy: [true; 4],
+            degraded_policy: DegradedPolicy::default(),
+
+            stall_thresholds: StallThresholds::default(),
+            stall_protection: None,
+            stalled_since: None,
+
+            odometry,
+            last_odometry_update: Rc::new(Cell::new(Instant::now())),
+            odometry_period: Rc::new(Cell::new(Some(ODOMETRY_PERIOD))),
+            odometry_rate: Rc::new(Cell::new(0.0)),
+            warned_odometry_stale: false,
+            warned_heading_drift: false,
+            kinematics,
+            module_positions,
+            gyro,
+            heading_offset: Rc::new(Cell::new(0.0)),
+            gyro_zero: 0.0,
+            field_origin: Alliance::default(),
+            warned_gyro_not_ready: false,
+
+            heading_setpoint: None,
+            heading_error: HeadingErrorStats::default(),
+            start_pose: Vector3::zeros(),
+            pose_target: None,
+            heading_profile: TrapezoidProfile::new(
+                HEADING_PROFILE_MAX_VELOCITY,
+                HEADING_PROFILE_MAX_ACCEL,
+            )?,
+            heading_output_fraction: HEADING_OUTPUT_FRACTION,
+            holonomic_controller: HolonomicController::new(
+                PidController::new(TRANSLATION_P, 0.0, 0.0),
+                PidController::new(TRANSLATION_P, 0.0, 0.0),
+                PidController::new(HEADING_P, 0.0, 0.0),
+            ),
+            trajectory_end: Rc::new(Cell::new(None)),
+            anti_tip_threshold: None,
+
+            speed_deadband: SPEED_DEADBAND,
+            drive_ka: DRIVE_KA,
+            translation_deadband: TRANSLATION_DEADBAND,
+            traction_control: false,
+            rotation_compensation: false,
+            rotation_compensation_gain: ROTATION_COMPENSATION_GAIN,
+            cardinal_snap_tolerance: CARDINAL_SNAP_TOLERANCE,
+            heading_limits: None,
+            pre_align: true,
+            commanded_speeds: ChassisSpeeds::default(),
+
+            pose_publisher: Rc::new(RefCell::new(None)),
+            nt_command_mode: false,
+            pose_history: VecDeque::with_capacity(POSE_HISTORY_LEN),
+            input_recording: None,
+            datalog: None,
+
+            modules,
+        })
+    }
+}
+
+impl FailableDefault for Drivetrain {
+    fn failable_default() -> anyhow::Result<Self> {
+        Self::with_gyro(NavX::new(hal::spi::RioSPI::new(hal::spi::Port::MXP)?, 60))
+    }
+}
+
+impl ControlSafe for Drivetrain {
+    fn stop(&mut self) {
+        for module in &mut self.modules {
+            module.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        pin::{pin, Pin},
+        task::{Context, Poll},
+    };
+
+    use futures::{executor::block_on, task::noop_waker_ref};
+
+    use super::*;
+    use crate::{
+        gyro::FakeGyro,
+        sim::{sim_drivetrain, SimModule},
+        trajectory::{EventTrigger, TrajectorySample},
+    };
+
+    fn sim() -> (Drivetrain, FakeGyro, [SimModule; 4]) {
+        let gyro = FakeGyro::new();
+        let (drivetrain, modules) = sim_drivetrain(
+            gyro.clone(),
+            module_positions_from_dimensions(TRACK_WIDTH, WHEEL_BASE),
+        )
+        .unwrap();
+
+        (drivetrain, gyro, modules)
+    }
+
+    /// Robot relative wheel velocity of each module's last target, which doesn't depend on
+    /// whether optimize flipped it
+    fn target_vectors(drivetrain: &Drivetrain) -> [Vector2<f32>; 4] {
+        drivetrain.modules.each_ref().map(|module| {
+            let state = module.target_state();
+            Vector2::new(state.angle.cos(), state.angle.sin()) * state.drive
+        })
+    }
+
+    /// Records every value published to it, or fails every publish
+    #[derive(Default)]
+    struct RecordingTable {
+        published: RefCell<Vec<(String, Vec<f64>)>>,
+        entries: RefCell<Vec<(&'static str, f64)>>,
+        failing: bool,
+    }
+
+    impl NetworkTable for RecordingTable {
+        fn set_f64_array(&self, key: &str, value: &[f64]) -> anyhow::Result<()> {
+            ensure!(!self.failing, "Table is unreachable");
+
+            self.published
+                .borrow_mut()
+                .push((key.to_owned(), value.to_vec()));
+
+            Ok(())
+        }
+
+        fn get_f64(&self, key: &str) -> Option<f64> {
+            self.entries
+                .borrow()
+                .iter()
+                .find(|(entry, _)| *entry == key)
+                .map(|(_, value)| *value)
+        }
+    }
+
+    /// Polls a drivetrain routine once, stepping it one loop without an executor
+    fn poll_once<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    /// Holds still at the origin for `duration` seconds
+    fn still_trajectory(duration: f32) -> Trajectory {
+        let sample = |time| TrajectorySample {
+            time,
+            pose: Vector3::zeros(),
+            velocity: Vector3::zeros(),
+            heading_interpolation: Default::default(),
+        };
+
+        Trajectory::new(vec![sample(0.0), sample(duration)]).unwrap()
+    }
+
+    fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected} within {tolerance}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn field_oriented_drive_rotates_by_heading() {
+        let (mut drivetrain, gyro, modules) = sim();
+
+        // Clockwise degrees, so the robot faces field left
+        gyro.set_heading(-90.0);
+        drivetrain
+            .set_chassis_speeds(ChassisSpeeds::new(1.0, 0.0, 0.0), false)
+            .unwrap();
+
+        for vector in target_vectors(&drivetrain) {
+            assert_near(vector.x, 0.0, 1e-4);
+            assert_near(vector.y, -1.0, 1e-4);
+        }
+
+        for module in &modules {
+            assert_near(module.state().drive_velocity.abs(), 1.0, 1e-4);
+        }
+    }
+
+    #[test]
+    fn realign_heading_takes_the_gyro_heading() {
+        let (mut drivetrain, gyro, _) = sim();
+
+        drivetrain.reset_pose(Vector3::new(1.0, 2.0, 0.0));
+        gyro.set_heading(-30.0);
+        drivetrain.realign_heading();
+
+        let pose = drivetrain.get_pose();
+        assert_near(pose.x, 1.0, 1e-5);
+        assert_near(pose.y, 2.0, 1e-5);
+        assert_near(pose.z, 30f32.to_radians(), 1e-5);
+    }
+
+    #[test]
+    fn blend_weights_each_channel_and_clamps() {
+        let driver = DriveCommand {
+            drive: Vector2::new(1.0, 0.0),
+            turn_rate: 0.8,
+        };
+        let assist = DriveCommand {
+            drive: Vector2::new(0.0, 1.0),
+            turn_rate: -0.4,
+        };
+
+        let blended = driver.blend(
+            assist,
+            BlendWeights {
+                driver_translation: 0.5,
+                assist_translation: 0.5,
+                driver_rotation: 1.0,
+                assist_rotation: 0.5,
+            },
+        );
+        assert_near(blended.drive.x, 0.5, 1e-6);
+        assert_near(blended.drive.y, 0.5, 1e-6);
+        assert_near(blended.turn_rate, 0.6, 1e-6);
+
+        let saturated = driver.blend(
+            DriveCommand {
+                drive: Vector2::new(1.0, 0.0),
+                turn_rate: 1.0,
+            },
+            BlendWeights {
+                driver_translation: 1.0,
+                assist_translation: 1.0,
+                driver_rotation: 1.0,
+                assist_rotation: 1.0,
+            },
+        );
+        assert_near(saturated.drive.norm(), 1.0, 1e-6);
+        assert_near(saturated.drive.x, 1.0, 1e-6);
+        assert_near(saturated.turn_rate, 1.0, 1e-6);
+    }
+
+    #[test]
+    fn commands_inside_the_speed_deadband_hold_module_angles() {
+        let (mut drivetrain, _, modules) = sim();
+
+        drivetrain
+            .set_chassis_speeds_robot_relative(ChassisSpeeds::new(0.0, 0.5, 0.0), false)
+            .unwrap();
+        let angles = modules.each_ref().map(|module| module.state().turn_angle);
+
+        drivetrain
+            .set_chassis_speeds_robot_relative(ChassisSpeeds::new(0.001, 0.0, 0.001), false)
+            .unwrap();
+
+        for (module, angle) in modules.iter().zip(angles) {
+            assert_near(module.state().turn_angle, angle, 1e-6);
+            assert_near(module.state().drive_target, 0.0, 1e-6);
+        }
+        assert_eq!(
+            drivetrain.get_commanded_chassis_speeds(),
+            ChassisSpeeds::default()
+        );
+    }
+
+    #[test]
+    fn distance_and_heading_error_to_targets() {
+        let (mut drivetrain, _, _) = sim();
+
+        drivetrain.reset_pose(Vector3::new(1.0, 1.0, 3.0));
+
+        assert_near(drivetrain.distance_to(Vector2::new(4.0, 5.0)), 5.0, 1e-5);
+        // The short way across the wrap at PI, not nearly a full turn back
+        assert_near(drivetrain.heading_error_to(-3.0), TAU - 6.0, 1e-5);
+        assert_near(drivetrain.heading_error_to(2.5), -0.5, 1e-5);
+    }
+
+    #[test]
+    fn limited_chassis_speeds_ramp_and_unlimited_jump() {
+        let (mut drivetrain, _, _) = sim();
+        drivetrain.set_loop_period(Some(Duration::from_millis(20)));
+
+        let speeds = ChassisSpeeds::new(1.0, 0.0, 0.0);
+
+        drivetrain.set_chassis_speeds(speeds, true).unwrap();
+        assert_near(
+            drivetrain.get_commanded_chassis_speeds().vx,
+            MAX_ACCEL * 0.02,
+            1e-5,
+        );
+
+        drivetrain.set_chassis_speeds(speeds, true).unwrap();
+        assert_near(
+            drivetrain.get_commanded_chassis_speeds().vx,
+            2.0 * MAX_ACCEL * 0.02,
+            1e-5,
+        );
+
+        drivetrain.set_chassis_speeds(speeds, false).unwrap();
+        assert_near(drivetrain.get_commanded_chassis_speeds().vx, 1.0, 1e-5);
+    }
+
+    #[test]
+    fn arc_speeds_fit_the_limits_on_the_same_arc() {
+        // Tight enough that the turn rate is the limit
+        let tight = arc_speeds(0.5, 1.0);
+        assert_near(tight.vx, 0.5, 1e-6);
+        assert_near(tight.omega, MAX_ROTATION_LIMIT, 1e-6);
+
+        // Wide enough that the speed is the limit
+        let wide = arc_speeds(2.0, 3.0);
+        assert_near(wide.vx, MAX_VELOCITY_LIMIT, 1e-6);
+        assert_near(wide.omega, MAX_VELOCITY_LIMIT / 2.0, 1e-6);
+
+        let clockwise_reverse = arc_speeds(-0.5, -1.0);
+        assert_near(clockwise_reverse.vx, -0.5, 1e-6);
+        assert_near(clockwise_reverse.omega, MAX_ROTATION_LIMIT, 1e-6);
+
+        for speeds in [tight, wide, clockwise_reverse] {
+            assert_eq!(speeds.vy, 0.0);
+        }
+    }
+
+    #[test]
+    fn drive_arc_rejects_degenerate_radii() {
+        let (mut drivetrain, _, _) = sim();
+
+        for radius in [0.0, f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert!(drivetrain.drive_arc(radius, 0.5).is_err());
+        }
+        assert!(drivetrain.drive_arc(1.0, 0.5).is_ok());
+    }
+
+    #[test]
+    fn scale_to_voltage_accelerates_the_same_at_any_loop_rate() {
+        let (mut drivetrain, _, _) = sim();
+        drivetrain.set_drive_ka(0.5);
+
+        for dt in [0.02, 0.01] {
+            drivetrain.set_loop_period(Some(Duration::from_secs_f32(dt)));
+
+            // Far from the target, so acceleration is capped at 2 m/s^2 at either rate
+            let volts = drivetrain.scale_to_voltage(0.0, 1.0, 2.0);
+            assert_near(volts, DRIVE_KV * 2.0 * dt + 0.5 * 2.0, 1e-5);
+
+            // Close enough to reach the target within one loop
+            let volts = drivetrain.scale_to_voltage(1.0, 1.0 + dt, 2.0);
+            assert_near(volts, DRIVE_KV * (1.0 + dt) + 0.5, 1e-4);
+        }
+    }
+
+    #[test]
+    fn heading_limits_stop_at_the_boundary_and_turn_back() {
+        let (mut drivetrain, gyro, _) = sim();
+        drivetrain.set_loop_period(Some(Duration::from_millis(20)));
+        drivetrain.set_heading_limits(Some((0.0, FRAC_PI_2)));
+
+        // Inside the arc, 0.1 rad from the min boundary
+        gyro.set_heading(-0.1f32.to_degrees());
+        assert_near(drivetrain.limit_turn_rate(-10.0), -0.1 / 0.02, 1e-3);
+        assert_near(drivetrain.limit_turn_rate(1.0), 1.0, 1e-6);
+
+        // Outside, past the max boundary, so only turning back clockwise is allowed
+        gyro.set_heading(-2.0f32.to_degrees());
+        assert_eq!(drivetrain.limit_turn_rate(1.0), 0.0);
+        assert_eq!(drivetrain.limit_turn_rate(-1.0), -1.0);
+
+        drivetrain.set_heading_limits(None);
+        assert_eq!(drivetrain.limit_turn_rate(1.0), 1.0);
+    }
+
+    #[test]
+    fn trajectory_events_fire_once_in_trigger_order() {
+        let (mut drivetrain, _, _) = sim();
+        let trajectory = still_trajectory(0.02);
+        let fired = RefCell::new(Vec::new());
+
+        let event = |trigger, name| {
+            let fired = &fired;
+            TrajectoryEvent::new(trigger, move || fired.borrow_mut().push(name))
+        };
+
+        block_on(drivetrain.follow_trajectory_with_events(
+            &trajectory,
+            vec![
+                event(EventTrigger::Time(10.0), "past the end"),
+                event(EventTrigger::Fraction(0.5), "halfway"),
+                event(EventTrigger::Time(0.0), "start"),
+            ],
+        ))
+        .unwrap();
+
+        assert_eq!(fired.into_inner(), ["start", "halfway", "past the end"]);
+    }
+
+    #[test]
+    fn odometry_closes_a_full_circle_across_the_heading_wrap() {
+        let (drivetrain, gyro, _) = sim();
+        let mut heading = AngleUnwrapper::default();
+
+        let steps = 1000;
+        let dt = TAU / steps as f32;
+
+        // Forward at 1 m/s while turning at 1 rad/s, a 1 m radius circle back to the start
+        for step in 1..=steps {
+            let states = drivetrain.kinematics.inverse(Vector3::new(dt, 0.0, dt));
+
+            gyro.set_heading(-(step as f32 * dt).to_degrees());
+            drivetrain.update_odometry(states, heading.update(drivetrain.get_heading()));
+        }
+
+        let pose = drivetrain.get_pose();
+        assert_near(pose.x, 0.0, 0.02);
+        assert_near(pose.y, 0.0, 0.02);
+        assert_near(wrap_angle(pose.z), 0.0, 0.01);
+    }
+
+    #[test]
+    fn pose_is_published_as_field2d() {
+        let (mut drivetrain, _, _) = sim();
+        let table = Rc::new(RecordingTable::default());
+
+        let publisher: Rc<dyn NetworkTable> = table.clone();
+        drivetrain.set_pose_publisher(Some((publisher, "/field/robot".to_owned())));
+        drivetrain.reset_pose(Vector3::new(1.5, -2.0, FRAC_PI_2));
+        drivetrain.publish_pose();
+
+        let published = table.published.borrow();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].0, "/field/robot");
+
+        let value = &published[0].1;
+        assert!((value[0] - 1.5).abs() < 1e-5);
+        assert!((value[1] + 2.0).abs() < 1e-5);
+        assert!((value[2] - 90.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn failed_pose_publishes_dont_stop_driving() {
+        let (mut drivetrain, _, _) = sim();
+        let table: Rc<dyn NetworkTable> = Rc::new(RecordingTable {
+            failing: true,
+            ..Default::default()
+        });
+
+        drivetrain.set_pose_publisher(Some((table, "/field/robot".to_owned())));
+        drivetrain.publish_pose();
+
+        assert!(drivetrain
+            .set_chassis_speeds(ChassisSpeeds::new(0.5, 0.0, 0.0), false)
+            .is_ok());
+        assert_near(drivetrain.get_commanded_chassis_speeds().vx, 0.5, 1e-5);
+    }
+
+    #[test]
+    fn field_origin_rotates_heading_for_red() {
+        let (mut drivetrain, gyro, _) = sim();
+
+        gyro.set_heading(-30.0);
+        let blue = drivetrain.get_heading();
+        assert_near(blue, 30f32.to_radians(), 1e-5);
+
+        drivetrain.set_field_origin(Alliance::Red);
+        assert_near(drivetrain.get_heading(), blue + PI, 1e-5);
+        assert_near(drivetrain.get_pose().z, blue + PI, 1e-5);
+
+        // Composes with zeroing: facing red field forward reads as zero
+        drivetrain.reset_pose(Vector3::zeros());
+        assert_near(wrap_angle(drivetrain.get_heading()), 0.0, 1e-5);
+
+        drivetrain.set_field_origin(Alliance::Blue);
+        assert_near(wrap_angle(drivetrain.get_heading() - PI), 0.0, 1e-5);
+    }
+
+    #[test]
+    fn traction_control_limits_wheels_past_the_measured_motion() {
+        let (mut drivetrain, _, modules) = sim();
+        drivetrain.set_traction_control(true);
+
+        // From rest only the minimum speed is allowed
+        drivetrain
+            .set_chassis_speeds_robot_relative(ChassisSpeeds::new(1.0, 0.0, 0.0), false)
+            .unwrap();
+        for module in &modules {
+            assert_near(module.state().drive_target, TRACTION_MIN_SPEED, 1e-5);
+        }
+
+        // Once the chassis is measured moving along, the full command goes through
+        for module in &modules {
+            module.update(|state| state.drive_velocity = 1.0);
+        }
+        drivetrain
+            .set_chassis_speeds_robot_relative(ChassisSpeeds::new(1.0, 0.0, 0.0), false)
+            .unwrap();
+        for module in &modules {
+            assert_near(module.state().drive_target, 1.0, 1e-5);
+        }
+
+        drivetrain.set_traction_control(false);
+        for module in &modules {
+            module.update(|state| state.drive_velocity = 0.0);
+        }
+        drivetrain
+            .set_chassis_speeds_robot_relative(ChassisSpeeds::new(1.0, 0.0, 0.0), false)
+            .unwrap();
+        for module in &modules {
+            assert_near(module.state().drive_target, 1.0, 1e-5);
+        }
+    }
+
+    #[test]
+    fn pre_align_steers_before_driving() {
+        let (mut drivetrain, _, modules) = sim();
+
+        // The wheels start forward and won't steer until released
+        for module in &modules {
+            module.update(|state| state.frozen = true);
+        }
+
+        let mut drive = pin!(drivetrain.drive_to_pose(Vector3::new(0.0, 1.0, 0.0), 0.01));
+        assert!(poll_once(drive.as_mut()).is_pending());
+
+        for module in &modules {
+            let state = module.state();
+            assert_near(state.turn_target.rem_euclid(PI), FRAC_PI_2, 1e-3);
+            assert_eq!(state.drive_target, 0.0);
+        }
+
+        for module in &modules {
+            module.update(|state| {
+                state.frozen = false;
+                state.turn_angle = normalize_angle(state.turn_target);
+            });
+        }
+        assert!(poll_once(drive.as_mut()).is_pending());
+
+        for module in &modules {
+            assert!(module.state().drive_target.abs() > 0.0);
+        }
+    }
+
+    #[test]
+    fn translation_deadband_must_leave_room_to_drive() {
+        let (mut drivetrain, _, _) = sim();
+
+        assert!(drivetrain.set_translation_deadband(1.0).is_err());
+        assert!(drivetrain.set_translation_deadband(-0.1).is_err());
+        assert!(drivetrain.set_translation_deadband(f32::NAN).is_err());
+
+        drivetrain.set_translation_deadband(0.5).unwrap();
+        assert_eq!(
+            drivetrain.apply_deadband(Vector2::new(0.4, 0.0)),
+            Vector2::zeros()
+        );
+        assert_near(
+            drivetrain.apply_deadband(Vector2::new(1.0, 0.0)).x,
+            1.0,
+            1e-6,
+        );
+    }
+
+    #[test]
+    fn rotation_compensation_counters_yaw_during_pure_translation() {
+        let (mut drivetrain, gyro, _) = sim();
+        let drive = Vector2::new(1.0, 0.0);
+
+        // Yawing counterclockwise at 1 rad/s
+        gyro.set_rate(-1.0f32.to_degrees());
+
+        assert_eq!(drivetrain.compensate_rotation(drive, 0.0), 0.0);
+
+        drivetrain.set_rotation_compensation(true);
+        assert_near(
+            drivetrain.compensate_rotation(drive, 0.0),
+            -ROTATION_COMPENSATION_GAIN,
+            1e-5,
+        );
+
+        drivetrain.set_rotation_compensation_gain(2.0);
+        assert_near(drivetrain.compensate_rotation(drive, 0.0), -2.0, 1e-5);
+
+        // Commanded turns and standing still are left alone
+        assert_eq!(drivetrain.compensate_rotation(drive, 0.5), 0.5);
+        assert_eq!(drivetrain.compensate_rotation(Vector2::zeros(), 0.0), 0.0);
+    }
+
+    #[test]
+    fn accel_feedforward_follows_acceleration_along_each_wheel() {
+        let (mut drivetrain, _, _) = sim();
+        let accel = Vector3::new(2.0, 0.0, 0.0);
+        let states = |angle| [SwerveState::new(angle, 1.0); 4];
+
+        assert_eq!(drivetrain.accel_feedforward(&states(0.0), accel), [0.0; 4]);
+
+        drivetrain.set_drive_ka(0.5);
+        let expected = 0.5 / DRIVE_KV * 2.0;
+
+        for feedforward in drivetrain.accel_feedforward(&states(0.0), accel) {
+            assert_near(feedforward, expected, 1e-5);
+        }
+
+        // Reversed wheels push the other way, sideways wheels can't help
+        for feedforward in drivetrain.accel_feedforward(&states(PI), accel) {
+            assert_near(feedforward, -expected, 1e-5);
+        }
+        for feedforward in drivetrain.accel_feedforward(&states(FRAC_PI_2), accel) {
+            assert_near(feedforward, 0.0, 1e-5);
+        }
+
+        for feedforward in drivetrain.accel_feedforward(&states(0.0), Vector3::zeros()) {
+            assert_near(feedforward, 0.0, 1e-6);
+        }
+    }
+
+    #[test]
+    fn stall_protection_cuts_translation_while_pinned() {
+        let (mut drivetrain, _, modules) = sim();
+        let speeds = ChassisSpeeds::new(0.5, 0.0, 0.0);
+
+        drivetrain.set_stall_protection(true, Duration::ZERO);
+        drivetrain.set_chassis_speeds(speeds, false).unwrap();
+        assert!(!drivetrain.is_stalled());
+
+        // Pinned against a wall: the wheels stop and the current climbs
+        for module in &modules {
+            module.update(|state| {
+                state.frozen = true;
+                state.drive_velocity = 0.0;
+                state.drive_current = 50.0;
+            });
+        }
+        assert!(drivetrain.is_stalled());
+
+        drivetrain.set_chassis_speeds(speeds, false).unwrap();
+        assert_near(
+            drivetrain.get_commanded_chassis_speeds().vx,
+            0.5 * STALL_OUTPUT_SCALE,
+            1e-5,
+        );
+
+        // Free again
+        for module in &modules {
+            module.update(|state| {
+                state.frozen = false;
+                state.drive_velocity = 0.5;
+                state.drive_current = 10.0;
+            });
+        }
+        drivetrain.set_chassis_speeds(speeds, false).unwrap();
+        assert_near(drivetrain.get_commanded_chassis_speeds().vx, 0.5, 1e-5);
+    }
+
+    #[test]
+    fn field_and_robot_frames_are_inverses() {
+        let (drivetrain, gyro, _) = sim();
+
+        // Facing field left
+        gyro.set_heading(-90.0);
+
+        let robot = drivetrain.field_to_robot(Vector2::new(1.0, 0.0));
+        assert_near(robot.x, 0.0, 1e-5);
+        assert_near(robot.y, -1.0, 1e-5);
+
+        let field = drivetrain.robot_to_field(Vector2::new(1.0, 0.0));
+        assert_near(field.x, 0.0, 1e-5);
+        assert_near(field.y, 1.0, 1e-5);
+
+        gyro.set_heading(-30.0);
+        let vector = Vector2::new(0.3, -0.7);
+        let round_trip = drivetrain.robot_to_field(drivetrain.field_to_robot(vector));
+        assert_near(round_trip.x, vector.x, 1e-5);
+        assert_near(round_trip.y, vector.y, 1e-5);
+    }
+
+    #[test]
+    fn soft_start_ramps_the_speed_cap_only_when_set() {
+        let (mut drivetrain, _, _) = sim();
+        let at_full_stick = |drivetrain: &mut Drivetrain| {
+            drivetrain.x_limit.reset(1.0);
+            drivetrain.y_limit.reset(1.0);
+            drivetrain.angle_limit.reset(1.0);
+
+            drivetrain.limit(Vector2::new(1.0, 1.0), 1.0)
+        };
+
+        // Without a soft start the limits are untouched, diagonals included
+        drivetrain.set_enabled(true).unwrap();
+        assert_eq!(drivetrain.soft_start_cap(), None);
+        let (drive, turn_rate) = at_full_stick(&mut drivetrain);
+        assert_near(drive.x, MAX_VELOCITY_LIMIT, 1e-6);
+        assert_near(drive.y, MAX_VELOCITY_LIMIT, 1e-6);
+        assert_near(turn_rate, MAX_ROTATION_LIMIT, 1e-6);
+
+        // Just enabled, the ramp starts from its initial fraction
+        drivetrain.set_soft_start(Some(Duration::from_secs(3600)));
+        drivetrain.set_enabled(true).unwrap();
+        let (drive, turn_rate) = at_full_stick(&mut drivetrain);
+        assert_near(drive.norm(), MAX_VELOCITY_LIMIT * SOFT_START_INITIAL, 1e-4);
+        assert_near(turn_rate, MAX_ROTATION_LIMIT * SOFT_START_INITIAL, 1e-4);
+
+        // A finished ramp allows the full limits
+        drivetrain.set_soft_start(Some(Duration::ZERO));
+        assert_eq!(drivetrain.soft_start_cap(), Some(1.0));
+        let (drive, _) = at_full_stick(&mut drivetrain);
+        assert_near(drive.norm(), MAX_VELOCITY_LIMIT, 1e-6);
+    }
+
+    #[test]
+    fn collision_abort_keeps_a_custom_threshold() {
+        let (mut drivetrain, gyro, _) = sim();
+
+        // Below the default threshold but above the custom one
+        gyro.set_acceleration(Vector2::new(0.8, 0.0));
+
+        drivetrain.set_collision_threshold(0.5);
+        drivetrain.set_collision_abort(false);
+        assert!(drivetrain.check_collision().is_ok());
+
+        drivetrain.set_collision_abort(true);
+        let err =
+            block_on(drivetrain.drive_to_pose(Vector3::new(1.0, 0.0, 0.0), 0.01)).unwrap_err();
+        let collision = err.downcast_ref::<CollisionDetected>().unwrap();
+        assert_near(collision.acceleration, 0.8, 1e-6);
+    }
+
+    #[test]
+    fn current_mode_only_drives_through_module_currents() {
+        let (mut drivetrain, _, modules) = sim();
+
+        drivetrain
+            .set_drive_control_mode(DriveControlMode::Current)
+            .unwrap();
+
+        assert!(drivetrain
+            .set_chassis_speeds(ChassisSpeeds::new(0.5, 0.0, 0.0), false)
+            .is_err());
+        assert!(drivetrain.set_input_raw(Vector2::zeros(), 0.0).is_err());
+        assert!(drivetrain
+            .set_modules([SwerveState::new(0.0, 0.5); 4])
+            .is_err());
+        for module in &modules {
+            assert_eq!(module.state().drive_target, 0.0);
+        }
+
+        drivetrain
+            .set_module_drive_currents([SwerveState::new(0.0, 10.0); 4])
+            .unwrap();
+        for module in &modules {
+            let state = module.state();
+            assert_eq!(state.drive_mode, DriveControlMode::Current);
+            assert_eq!(state.drive_target, 10.0);
+        }
+
+        drivetrain
+            .set_drive_control_mode(DriveControlMode::Velocity)
+            .unwrap();
+        drivetrain
+            .set_chassis_speeds(ChassisSpeeds::new(0.5, 0.0, 0.0), false)
+            .unwrap();
+        for module in &modules {
+            assert_near(module.state().drive_target, 0.5, 1e-5);
+        }
+    }
+
+    #[test]
+    fn instant_stop_on_release_skips_the_ramp_down() {
+        let (mut drivetrain, _, _) = sim();
+        let full_speed = |drivetrain: &mut Drivetrain| {
+            drivetrain.x_limit.reset(1.0);
+            drivetrain.angle_limit.reset(1.0);
+        };
+
+        // Off, releasing the stick ramps down through the slew limiters
+        full_speed(&mut drivetrain);
+        drivetrain.set_input(Vector2::zeros(), 0.0).unwrap();
+        let speeds = drivetrain.get_commanded_chassis_speeds();
+        assert!(speeds.vx > 0.0 && speeds.vx < MAX_VELOCITY_LIMIT);
+        assert!(speeds.omega > 0.0 && speeds.omega < MAX_ROTATION_LIMIT);
+
+        drivetrain.set_instant_stop_on_release(true);
+        full_speed(&mut drivetrain);
+        drivetrain.set_input(Vector2::zeros(), 0.0).unwrap();
+        assert_eq!(
+            drivetrain.get_commanded_chassis_speeds(),
+            ChassisSpeeds::default()
+        );
+
+        // Translation and rotation are released separately
+        full_speed(&mut drivetrain);
+        drivetrain.set_input(Vector2::new(1.0, 0.0), 0.0).unwrap();
+        let speeds = drivetrain.get_commanded_chassis_speeds();
+        assert_near(speeds.vx, MAX_VELOCITY_LIMIT, 1e-5);
+        assert_eq!(speeds.omega, 0.0);
+    }
+
+    #[test]
+    fn reset_slew_limiters_ramps_the_next_command_from_rest() {
+        let (mut drivetrain, _, _) = sim();
+
+        // Left at full speed by an earlier mode
+        drivetrain.x_limit.reset(1.0);
+        drivetrain.y_limit.reset(-1.0);
+        drivetrain.angle_limit.reset(1.0);
+        drivetrain.measure_loop_period();
+
+        drivetrain.reset_slew_limiters();
+        assert_eq!(drivetrain.x_limit.value(), 0.0);
+        assert_eq!(drivetrain.y_limit.value(), 0.0);
+        assert_eq!(drivetrain.angle_limit.value(), 0.0);
+        assert_eq!(drivetrain.last_loop, None);
+
+        // One nominal loop of acceleration from zero
+        drivetrain.set_input(Vector2::new(1.0, 0.0), 0.0).unwrap();
+        assert_near(
+            drivetrain.get_commanded_chassis_speeds().vx,
+            MAX_ACCEL * NOMINAL_LOOP_PERIOD.as_secs_f32() * MAX_VELOCITY_LIMIT,
+            1e-5,
+        );
+    }
+
+    #[test]
+    fn robot_relative_speeds_ignore_the_heading() {
+        let (mut drivetrain, gyro, _) = sim();
+
+        gyro.set_heading(-90.0);
+        drivetrain
+            .set_chassis_speeds_robot_relative(ChassisSpeeds::new(1.0, 0.0, 0.0), false)
+            .unwrap();
+
+        for vector in target_vectors(&drivetrain) {
+            assert_near(vector.x, 1.0, 1e-4);
+            assert_near(vector.y, 0.0, 1e-4);
+        }
+        assert_eq!(
+            drivetrain.get_commanded_chassis_speeds(),
+            ChassisSpeeds::new(1.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn heading_drift_compares_gyro_and_odometry_across_the_wrap() {
+        let (drivetrain, gyro, _) = sim();
+
+        gyro.set_heading(-30.0);
+        assert_near(drivetrain.heading_drift(), 30f32.to_radians(), 1e-5);
+
+        drivetrain.realign_heading();
+        assert_near(drivetrain.heading_drift(), 0.0, 1e-5);
+
+        // Just either side of facing backwards is only a small drift
+        gyro.set_heading(-179.0);
+        drivetrain
+            .odometry
+            .set_pose(Vector3::new(0.0, 0.0, -179f32.to_radians()));
+        assert_near(drivetrain.heading_drift(), -2f32.to_radians(), 1e-4);
+    }
+
+    #[test]
+    fn turn_wrapping_check_only_runs_when_armed() {
+        let (mut drivetrain, _, modules) = sim();
+
+        // A module that is stuck on the far side of the seam
+        modules[0].update(|state| {
+            state.frozen = true;
+            state.turn_angle = PI;
+        });
+
+        drivetrain.set_verify_turn_wrapping(false);
+        block_on(drivetrain.check_turn_wrapping()).unwrap();
+        assert_eq!(modules[0].state().turn_target, 0.0);
+
+        drivetrain.set_verify_turn_wrapping(true);
+        let err = block_on(drivetrain.check_turn_wrapping()).unwrap_err();
+        assert!(err.to_string().contains(MODULE_NAMES[0]));
+    }
+
+    #[test]
+    fn max_speeds_put_the_fastest_wheel_at_free_speed() {
+        let (drivetrain, _) = sim_drivetrain(
+            FakeGyro::new(),
+            [
+                Vector2::new(0.3, 0.3),
+                Vector2::new(0.3, -0.3),
+                Vector2::new(-0.5, 0.0),
+                Vector2::new(-0.3, -0.3),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(drivetrain.max_translational_speed(), DRIVE_FREE_SPEED);
+        assert_near(drivetrain.max_angular_speed(), DRIVE_FREE_SPEED / 0.5, 1e-5);
+
+        let fastest = drivetrain
+            .kinematics
+            .inverse(Vector3::new(0.0, 0.0, drivetrain.max_angular_speed()))
+            .iter()
+            .map(|state| state.drive.abs())
+            .fold(0.0, f32::max);
+        assert_near(fastest, DRIVE_FREE_SPEED, 1e-4);
+    }
+
+    #[test]
+    fn drive_velocity_errors_compare_like_for_like() {
+        let (mut drivetrain, _, modules) = sim();
+        let lag_behind = |speed| {
+            for module in &modules {
+                module.update(|state| {
+                    state.frozen = true;
+                    state.drive_velocity = speed;
+                });
+            }
+        };
+
+        drivetrain
+            .set_chassis_speeds(ChassisSpeeds::new(0.5, 0.0, 0.0), false)
+            .unwrap();
+        for error in drivetrain.drive_velocity_errors().unwrap() {
+            assert_near(error, 0.0, 1e-5);
+        }
+
+        lag_behind(0.3);
+        for error in drivetrain.drive_velocity_errors().unwrap() {
+            assert_near(error, 0.2, 1e-5);
+        }
+
+        // Backwards from facing forwards, so every wheel is flipped and driven in reverse
+        for module in &modules {
+            module.update(|state| state.frozen = false);
+        }
+        drivetrain
+            .set_chassis_speeds(ChassisSpeeds::new(-0.5, 0.0, 0.0), false)
+            .unwrap();
+        lag_behind(-0.3);
+        for error in drivetrain.drive_velocity_errors().unwrap() {
+            assert_near(error, -0.2, 1e-5);
+        }
+    }
+
+    #[test]
+    fn desaturate_gives_up_the_channel_the_policy_allows() {
+        let (mut drivetrain, _, _) = sim();
+        let speeds = ChassisSpeeds::new(1.2, 0.0, 2.0);
+        assert!(drivetrain.peak_module_speed(speeds.as_vector()) > DRIVE_FREE_SPEED);
+
+        let slow = ChassisSpeeds::new(0.5, 0.0, 0.5);
+        assert_eq!(drivetrain.desaturate(slow), slow);
+
+        let proportional = drivetrain.desaturate(speeds);
+        assert_near(proportional.vx / proportional.omega, 1.2 / 2.0, 1e-5);
+        assert_near(
+            drivetrain.peak_module_speed(proportional.as_vector()),
+            DRIVE_FREE_SPEED,
+            1e-4,
+        );
+
+        drivetrain.set_saturation_policy(SaturationPolicy::PreserveRotation);
+        let rotation = drivetrain.desaturate(speeds);
+        assert_near(rotation.omega, 2.0, 1e-5);
+        assert!(rotation.vx < proportional.vx);
+        assert_near(
+            drivetrain.peak_module_speed(rotation.as_vector()),
+            DRIVE_FREE_SPEED,
+            1e-4,
+        );
+
+        drivetrain.set_saturation_policy(SaturationPolicy::PreserveTranslation);
+        let translation = drivetrain.desaturate(speeds);
+        assert_near(translation.vx, 1.2, 1e-5);
+        assert!(translation.omega < proportional.omega);
+        assert_near(
+            drivetrain.peak_module_speed(translation.as_vector()),
+            DRIVE_FREE_SPEED,
+            1e-4,
+        );
+    }
+
+    #[test]
+    fn chassis_speeds_discretize_over_the_given_period() {
+        let (mut drivetrain, _, _) = sim();
+        let speeds = ChassisSpeeds::new(1.0, 0.0, 1.0);
+
+        for period in [0.01, 0.1, 0.5] {
+            drivetrain
+                .set_chassis_speeds_with_period(speeds, false, Duration::from_secs_f32(period))
+                .unwrap();
+
+            let expected = speeds.discretize(period);
+            let commanded = drivetrain.get_commanded_chassis_speeds();
+            assert_near(commanded.vx, expected.vx, 1e-5);
+            assert_near(commanded.vy, expected.vy, 1e-5);
+            assert_near(commanded.omega, expected.omega, 1e-5);
+        }
+
+        // The longer the period, the further the translation leads the turn
+        let short = speeds.discretize(0.01);
+        let long = speeds.discretize(0.5);
+        assert!(long.vy.abs() > short.vy.abs());
+    }
+
+    #[test]
+    fn turn_to_heading_ramps_up_without_odometry() {
+        let (mut drivetrain, _, modules) = sim();
+
+        // Nothing updates odometry here
+        std::thread::sleep(ODOMETRY_STALE_THRESHOLD);
+        assert!(drivetrain.odometry_staleness() > ODOMETRY_STALE_THRESHOLD);
+
+        // Wheel speed of the turn, proportional to the commanded turn rate
+        let mut speeds = Vec::new();
+        {
+            let mut turn = pin!(drivetrain.turn_to_heading(FRAC_PI_2));
+
+            for _ in 0..5 {
+                assert!(poll_once(turn.as_mut()).is_pending());
+                speeds.push(modules[0].state().drive_target.abs());
+                std::thread::sleep(NOMINAL_LOOP_PERIOD);
+            }
+        }
+
+        assert!(speeds.windows(2).all(|pair| pair[1] > pair[0]));
+
+        let radius = drivetrain.module_positions[0].norm();
+        assert!(speeds[0] < 0.5 * MAX_ROTATION_LIMIT * radius);
+    }
+
+    #[test]
+    fn input_frames_combine_independently() {
+        use InputFrame::{Field, Robot};
+
+        // Facing field left, with the limiters already at full speed
+        let (mut drivetrain, gyro, _) = sim();
+        gyro.set_heading(-90.0);
+        let mut drive = |translation, rotation, rotation_frame| {
+            drivetrain.x_limit.reset(1.0);
+            drivetrain.angle_limit.reset(0.0);
+            drivetrain
+                .set_input_with_frames(
+                    Vector2::new(1.0, 0.0),
+                    translation,
+                    rotation,
+                    rotation_frame,
+                )
+                .unwrap();
+
+            (
+                drivetrain.get_commanded_chassis_speeds(),
+                drivetrain.heading_setpoint,
+            )
+        };
+
+        // Field forward is robot right, robot forward is robot forward
+        let (speeds, setpoint) = drive(Field, 0.0, Robot);
+        assert_near(speeds.vx, 0.0, 1e-4);
+        assert!(speeds.vy < -0.5);
+        assert_eq!(setpoint, None);
+
+        let (speeds, setpoint) = drive(Robot, 0.0, Robot);
+        assert!(speeds.vx > 0.5);
+        assert_near(speeds.vy, 0.0, 1e-4);
+        assert_eq!(setpoint, None);
+
+        // A field heading of backwards is a counterclockwise turn from facing left
+        let (speeds, setpoint) = drive(Field, PI, Field);
+        assert!(speeds.vy < -0.5);
+        assert!(speeds.omega > 0.0);
+        assert_eq!(setpoint, Some(PI));
+
+        let (speeds, setpoint) = drive(Robot, PI, Field);
+        assert!(speeds.vx > 0.5);
+        assert!(speeds.omega > 0.0);
+        assert_eq!(setpoint, Some(PI));
+    }
+
+    #[test]
+    fn input_frames_release_like_set_input() {
+        let (mut drivetrain, _, _) = sim();
+        let release = |drivetrain: &mut Drivetrain| {
+            drivetrain
+                .set_input_with_frames(Vector2::zeros(), InputFrame::Robot, 0.0, InputFrame::Robot)
+                .unwrap();
+        };
+
+        drivetrain.set_instant_stop_on_release(true);
+        drivetrain.x_limit.reset(1.0);
+        release(&mut drivetrain);
+        assert_eq!(
+            drivetrain.get_commanded_chassis_speeds(),
+            ChassisSpeeds::default()
+        );
+
+        // Straight into the X-stance, where the wheels don't all point the same way
+        drivetrain.set_auto_brake(true, Duration::ZERO);
+        release(&mut drivetrain);
+        let angles = drivetrain
+            .modules
+            .each_ref()
+            .map(|module| module.target_state().angle);
+        assert!(angles
+            .iter()
+            .any(|angle| wrap_angle(angle - angles[0]).abs() > 0.1));
+    }
+
+    #[test]
+    fn heading_output_is_clamped_to_the_configured_fraction() {
+        let (mut drivetrain, _, _) = sim();
+
+        // A quarter turn left of the heading asks for more than the limit
+        assert_near(
+            drivetrain.heading_hold_output(FRAC_PI_2),
+            MAX_ROTATION_LIMIT,
+            1e-6,
+        );
+        assert_near(
+            drivetrain.heading_hold_output(-FRAC_PI_2),
+            -MAX_ROTATION_LIMIT,
+            1e-6,
+        );
+
+        drivetrain.set_heading_output_fraction(0.25).unwrap();
+        assert_near(
+            drivetrain.heading_hold_output(FRAC_PI_2),
+            0.25 * MAX_ROTATION_LIMIT,
+            1e-6,
+        );
+
+        // Small errors stay proportional
+        assert_near(drivetrain.heading_hold_output(0.01), HEADING_P * 0.01, 1e-5);
+
+        assert!(drivetrain.set_heading_output_fraction(0.0).is_err());
+        assert!(drivetrain.set_heading_output_fraction(1.5).is_err());
+    }
+
+    #[test]
+    fn update_odometry_integrates_in_the_field_frame() {
+        let (drivetrain, _, _) = sim();
+
+        std::thread::sleep(ODOMETRY_STALE_THRESHOLD);
+        assert!(drivetrain.odometry_staleness() >= ODOMETRY_STALE_THRESHOLD);
+
+        // Half a meter to the robot's left while facing field left
+        let states = drivetrain.kinematics.inverse(Vector3::new(0.0, 0.5, 0.0));
+        drivetrain.update_odometry(states, FRAC_PI_2);
+
+        let pose = drivetrain.get_pose();
+        assert_near(pose.x, -0.5, 1e-4);
+        assert_near(pose.y, 0.0, 1e-4);
+        assert_near(pose.z, FRAC_PI_2, 1e-5);
+        assert!(drivetrain.odometry_staleness() < ODOMETRY_STALE_THRESHOLD);
+    }
+
+    #[test]
+    fn anti_tip_only_slows_acceleration_that_tips_further() {
+        let (mut drivetrain, gyro, _) = sim();
+        let drive = |drivetrain: &mut Drivetrain, vx, vy| {
+            drivetrain
+                .set_chassis_speeds_robot_relative(ChassisSpeeds::new(vx, vy, 0.0), false)
+                .unwrap();
+            drivetrain.get_commanded_chassis_speeds().velocity()
+        };
+
+        // Off by default
+        gyro.set_pitch(20.0);
+        assert_near(drive(&mut drivetrain, 0.5, 0.0).x, 0.5, 1e-6);
+        drive(&mut drivetrain, 0.0, 0.0);
+
+        // Front up, so accelerating forward is cut back but slowing down isn't
+        drivetrain.set_anti_tip(true, 0.1);
+        assert_near(
+            drive(&mut drivetrain, 0.5, 0.0).x,
+            0.5 * ANTI_TIP_ACCEL_SCALE,
+            1e-6,
+        );
+        assert_near(drive(&mut drivetrain, 0.05, 0.0).x, 0.05, 1e-6);
+        assert_near(drive(&mut drivetrain, -0.5, 0.0).x, -0.5, 1e-6);
+
+        // Left side up limits accelerating left only
+        gyro.set_pitch(0.0);
+        gyro.set_roll(20.0);
+        let velocity = drive(&mut drivetrain, 0.5, 0.5);
+        assert_near(velocity.x, 0.5, 1e-6);
+        assert_near(velocity.y, 0.5 * ANTI_TIP_ACCEL_SCALE, 1e-6);
+    }
+
+    #[test]
+    fn velocity_and_heading_turns_toward_the_target() {
+        let (mut drivetrain, _, _) = sim();
+
+        drivetrain
+            .set_velocity_and_heading(Vector2::new(0.5, 0.0), FRAC_PI_2)
+            .unwrap();
+        let speeds = drivetrain.get_commanded_chassis_speeds();
+        assert_eq!(drivetrain.heading_setpoint, Some(FRAC_PI_2));
+        assert!(speeds.vx > 0.0);
+        assert!(speeds.omega > 0.0);
+
+        drivetrain.reset_slew_limiters();
+        drivetrain
+            .set_velocity_and_heading(Vector2::new(0.5, 0.0), -FRAC_PI_2)
+            .unwrap();
+        assert_eq!(drivetrain.heading_setpoint, Some(-FRAC_PI_2));
+        assert!(drivetrain.get_commanded_chassis_speeds().omega < 0.0);
+    }
+
+    #[test]
+    fn brake_hold_heading_leaves_the_x_to_turn_back() {
+        let (mut drivetrain, gyro, modules) = sim();
+        let driving = || {
+            modules
+                .iter()
+                .any(|module| module.state().drive_target.abs() > 0.0)
+        };
+
+        let mut hold = pin!(drivetrain.brake_hold_heading());
+
+        assert!(poll_once(hold.as_mut()).is_pending());
+        assert!(!driving());
+        let x_stance = modules.each_ref().map(|module| module.state().turn_target);
+
+        // Pushed clockwise past the tolerance, the wheels drive to turn back
+        gyro.set_heading(20.0);
+        assert!(poll_once(hold.as_mut()).is_pending());
+        assert!(driving());
+
+        gyro.set_heading(0.0);
+        assert!(poll_once(hold.as_mut()).is_pending());
+        assert!(!driving());
+        // Back in the X, though optimize may have flipped a wheel end for end
+        for (module, angle) in modules.iter().zip(x_stance) {
+            assert_near(
+                wrap_angle(2.0 * (module.state().turn_target - angle)),
+                0.0,
+                1e-4,
+            );
+        }
+    }
+
+    #[test]
+    fn wpilib_poses_mirror_for_red_and_round_trip() {
+        let pose = Vector3::new(1.0, 2.0, 1.5 * PI);
+
+        let blue = Alliance::Blue.to_wpilib(pose);
+        assert_near(blue.x, 1.0, 1e-6);
+        assert_near(blue.y, 2.0, 1e-6);
+        assert_near(blue.z, -FRAC_PI_2, 1e-5);
+
+        let red = Alliance::Red.to_wpilib(pose);
+        assert_near(red.x, FIELD_LENGTH - 1.0, 1e-5);
+        assert_near(red.y, FIELD_WIDTH - 2.0, 1e-5);
+        assert_near(red.z, FRAC_PI_2, 1e-5);
+
+        for alliance in [Alliance::Blue, Alliance::Red] {
+            let round_trip = alliance.from_wpilib(alliance.to_wpilib(pose));
+            assert_near(round_trip.x, pose.x, 1e-5);
+            assert_near(round_trip.y, pose.y, 1e-5);
+            assert_near(round_trip.z, pose.z, 1e-5);
+        }
+
+        let (mut drivetrain, _, _) = sim();
+        drivetrain.set_field_origin(Alliance::Red);
+        drivetrain.reset_pose(pose);
+        let wpilib = drivetrain.get_pose_in(PoseConvention::Wpilib);
+        assert_near(wpilib.x, red.x, 1e-5);
+        assert_near(wpilib.y, red.y, 1e-5);
+        assert_near(wpilib.z, red.z, 1e-5);
+    }
+
+    #[test]
+    fn drive_heading_holds_the_starting_heading() {
+        let (mut drivetrain, gyro, _) = sim();
+
+        // Facing field left, driving field forward
+        gyro.set_heading(-90.0);
+        {
+            let mut drive = pin!(drivetrain.drive_heading(0.0, 0.5, Duration::from_secs(3600)));
+            assert!(poll_once(drive.as_mut()).is_pending());
+
+            // Bumped clockwise
+            gyro.set_heading(-60.0);
+            assert!(poll_once(drive.as_mut()).is_pending());
+        }
+
+        // Turning back toward where it started
+        assert_near(drivetrain.heading_setpoint.unwrap(), FRAC_PI_2, 1e-5);
+        assert!(drivetrain.get_commanded_chassis_speeds().omega > 0.0);
+
+        block_on(drivetrain.drive_heading(0.0, 0.5, Duration::ZERO)).unwrap();
+        assert_eq!(drivetrain.heading_setpoint, None);
+        assert_eq!(
+            drivetrain.get_commanded_chassis_speeds(),
+            ChassisSpeeds::default()
+        );
+    }
+
+    #[test]
+    fn wait_until_settled_waits_for_every_module() {
+        let (mut drivetrain, _, modules) = sim();
+
+        drivetrain
+            .set_chassis_speeds(ChassisSpeeds::new(0.5, 0.0, 0.0), false)
+            .unwrap();
+
+        // One wheel still spinning up
+        modules[0].update(|state| {
+            state.frozen = true;
+            state.drive_velocity = 0.1;
+        });
+
+        let mut settle = pin!(drivetrain.wait_until_settled(0.05, 0.05, 0.05));
+        assert!(poll_once(settle.as_mut()).is_pending());
+        std::thread::sleep(SETTLE_TIME);
+        assert!(poll_once(settle.as_mut()).is_pending());
+
+        // Settled, but it has to stay that way for a while
+        modules[0].update(|state| state.drive_velocity = 0.5);
+        assert!(poll_once(settle.as_mut()).is_pending());
+        std::thread::sleep(SETTLE_TIME);
+        assert!(poll_once(settle.as_mut()).is_ready());
+    }
+
+    #[test]
+    fn explicit_module_positions_are_validated_and_used() {
+        let positions = module_positions_from_widths(0.6, 0.4, 0.5);
+        assert_eq!(positions[0], Vector2::new(0.25, 0.3));
+        assert_eq!(positions[3], Vector2::new(-0.25, -0.2));
+
+        assert!(sim_drivetrain(FakeGyro::new(), [Vector2::zeros(); 4]).is_err());
+        let mut bad = positions;
+        bad[2].x = f32::NAN;
+        assert!(sim_drivetrain(FakeGyro::new(), bad).is_err());
+
+        // Spinning in place, each wheel's speed follows its own distance from the center
+        let (drivetrain, _) = sim_drivetrain(FakeGyro::new(), positions).unwrap();
+        let states = drivetrain.kinematics.inverse(Vector3::new(0.0, 0.0, 1.0));
+        for (state, position) in states.iter().zip(positions) {
+            assert_near(state.drive.abs(), position.norm(), 1e-4);
+        }
+        assert_near(
+            drivetrain.max_angular_speed(),
+            DRIVE_FREE_SPEED / positions[0].norm(),
+            1e-4,
+        );
+    }
+
+    #[test]
+    fn emergency_stop_latches_every_way_to_move_the_modules() {
+        let (mut drivetrain, _, modules) = sim();
+        drivetrain.emergency_stop();
+
+        assert!(drivetrain.point_all(1.0).is_err());
+        assert!(drivetrain.calibrate_offsets(false).is_err());
+        assert!(block_on(drivetrain.characterize_turn()).is_err());
+        drivetrain.set_verify_turn_wrapping(true);
+        assert!(block_on(drivetrain.check_turn_wrapping()).is_err());
+        let report = block_on(drivetrain.run_self_test());
+        assert!(report
+            .modules
+            .iter()
+            .all(|result| *result == ModuleTestResult::Skipped));
+
+        drivetrain
+            .set_drive_control_mode(DriveControlMode::Current)
+            .unwrap();
+        assert!(drivetrain
+            .set_module_drive_currents([SwerveState::new(0.0, 10.0); 4])
+            .is_err());
+        drivetrain
+            .set_drive_control_mode(DriveControlMode::Velocity)
+            .unwrap();
+
+        for module in &modules {
+            assert_eq!(module.state().turn_voltage, None);
+            assert_eq!(module.state().drive_target, 0.0);
+            assert_near(module.state().turn_angle, 0.0, 1e-6);
+        }
+
+        assert!(drivetrain.set_offset(0, 1.0).is_err());
+
+        // Pose moves error out instead of spinning with every command ignored, whether or not
+        // they steer the modules first
+        let target = Vector3::new(1.0, 0.0, 0.0);
+        assert!(block_on(drivetrain.drive_to_pose(target, 0.01)).is_err());
+        let moving = Trajectory::new(vec![
+            TrajectorySample {
+                time: 0.0,
+                pose: Vector3::zeros(),
+                velocity: Vector3::new(0.0, 1.0, 0.0),
+                heading_interpolation: Default::default(),
+            },
+            TrajectorySample {
+                time: 1.0,
+                pose: Vector3::new(0.0, 1.0, 0.0),
+                velocity: Vector3::new(0.0, 1.0, 0.0),
+                heading_interpolation: Default::default(),
+            },
+        ])
+        .unwrap();
+        assert!(block_on(drivetrain.follow_trajectory(&moving)).is_err());
+        drivetrain.set_pre_align(false);
+        assert!(block_on(drivetrain.drive_to_pose(target, 0.01)).is_err());
+
+        for module in &modules {
+            assert_eq!(module.state().turn_target, 0.0);
+        }
+
+        drivetrain.clear_emergency_stop();
+        drivetrain.point_all(1.0).unwrap();
+        for module in &modules {
+            assert_near(module.state().turn_angle, 1.0, 1e-6);
+        }
+    }
+
+    #[test]
+    fn heading_hold_uses_the_holonomic_controller() {
+        let (mut drivetrain, _, _) = sim();
+
+        drivetrain.set_holonomic_controller(HolonomicController::new(
+            PidController::default(),
+            PidController::default(),
+            PidController::new(1.0, 0.0, 0.0),
+        ));
+        assert_near(drivetrain.heading_hold_output(0.01), 0.01, 1e-5);
+        assert_near(drivetrain.heading_hold_output(-0.2), -0.2, 1e-5);
+    }
+
+    #[test]
+    fn set_modules_reaches_each_module_in_order() {
+        let (mut drivetrain, _, modules) = sim();
+        let states = [
+            SwerveState::new(0.1, 0.5),
+            SwerveState::new(0.5, 1.0),
+            SwerveState::new(-0.4, 1.5),
+            SwerveState::new(1.2, 2.0),
+        ];
+
+        drivetrain.set_modules(states).unwrap();
+
+        for (module, state) in modules.iter().zip(states) {
+            assert_near(
+                module.state().turn_angle,
+                normalize_angle(state.angle),
+                1e-5,
+            );
+            assert_near(module.state().drive_target, state.drive, 1e-5);
+        }
+
+        // Optimize still applies, so a wheel asked to turn most of the way around drives backward
+        drivetrain
+            .set_modules([SwerveState::new(0.1 + PI, 1.0); 4])
+            .unwrap();
+        for module in &modules {
+            assert_near(module.state().drive_target, -1.0, 1e-5);
+        }
+    }
+
+    #[test]
+    fn trajectory_time_remaining_counts_down_while_following() {
+        let (mut drivetrain, _, _) = sim();
+        assert_eq!(drivetrain.trajectory_time_remaining(), None);
+
+        let trajectory = still_trajectory(10.0);
+        let trajectory_end = drivetrain.trajectory_end.clone();
+        let end = {
+            let mut follow = pin!(drivetrain.follow_trajectory(&trajectory));
+            assert!(poll_once(follow.as_mut()).is_pending());
+
+            trajectory_end.get()
+        };
+
+        // Stopping early clears it
+        assert_eq!(drivetrain.trajectory_time_remaining(), None);
+
+        // Pick up where the follower was, without it borrowing the drivetrain
+        trajectory_end.set(end);
+        let mut remaining = Vec::new();
+        for _ in 0..3 {
+            remaining.push(drivetrain.trajectory_time_remaining().unwrap());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        assert!(remaining[0] <= Duration::from_secs(10));
+        assert!(remaining[0] > Duration::from_secs(9));
+        assert!(remaining.windows(2).all(|pair| pair[1] < pair[0]));
+    }
+
+    #[test]
+    fn drive_position_baseline_shows_in_module_positions() {
+        let (mut drivetrain, _, _) = sim();
+
+        drivetrain.set_drive_position(1, 2.5).unwrap();
+        let positions = drivetrain.get_module_positions().unwrap();
+        assert_eq!(
+            positions.map(|position| position.drive),
+            [0.0, 2.5, 0.0, 0.0]
+        );
+
+        assert!(drivetrain.set_drive_position(4, 1.0).is_err());
+        assert!(drivetrain.set_drive_position(0, f32::NAN).is_err());
+    }
+
+    #[test]
+    fn is_moving_checks_wheels_and_gyro() {
+        let (mut drivetrain, gyro, modules) = sim();
+        let moving = |drivetrain: &mut Drivetrain| {
+            drivetrain
+                .is_moving(MOVING_LINEAR_THRESHOLD, MOVING_ANGULAR_THRESHOLD)
+                .unwrap()
+        };
+
+        assert!(!moving(&mut drivetrain));
+
+        // Noise below the thresholds
+        for module in &modules {
+            module.update(|state| state.drive_velocity = 0.01);
+        }
+        gyro.set_rate(1.0);
+        assert!(!moving(&mut drivetrain));
+
+        for module in &modules {
+            module.update(|state| state.drive_velocity = 1.0);
+        }
+        assert!(moving(&mut drivetrain));
+
+        // Spinning in place with the wheels still reads as moving from the gyro
+        for module in &modules {
+            module.update(|state| state.drive_velocity = 0.0);
+        }
+        gyro.set_rate(-30.0);
+        assert!(moving(&mut drivetrain));
+    }
+
+    #[test]
+    fn recorded_inputs_replay_and_brake() {
+        let (mut drivetrain, _, modules) = sim();
+        assert!(drivetrain.stop_input_recording().is_none());
+
+        drivetrain.start_input_recording();
+        drivetrain.set_input(Vector2::new(1.0, 0.0), 0.0).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        drivetrain.set_input(Vector2::new(0.5, 0.0), 0.0).unwrap();
+        let trace = drivetrain.stop_input_recording().unwrap();
+
+        let inputs = trace
+            .samples()
+            .iter()
+            .map(|sample| sample.drive.x)
+            .collect::<Vec<_>>();
+        assert_eq!(inputs, [1.0, 0.5]);
+        assert!(trace.duration() >= 0.02);
+
+        // Replaying doesn't add to a stopped recording
+        let trace = InputTrace::from_csv(&trace.to_csv()).unwrap();
+        block_on(drivetrain.replay_inputs(&trace)).unwrap();
+        assert!(drivetrain.stop_input_recording().is_none());
+
+        // It ends in the X
+        for (module, brake) in modules.iter().zip(drivetrain.kinematics.brake()) {
+            let state = module.state();
+            assert_eq!(state.drive_target, 0.0);
+            assert_near(
+                wrap_angle(2.0 * (state.turn_target - brake.angle)),
+                0.0,
+                1e-4,
+            );
+        }
+    }
+
+    #[test]
+    fn nt_commands_drive_until_the_driver_takes_over() {
+        let (mut drivetrain, _, modules) = sim();
+        let table = RecordingTable::default();
+        table.entries.borrow_mut().push(("vy", 0.5));
+
+        // Already ramped up to the commanded speed
+        drivetrain.y_limit.reset(0.5 / MAX_VELOCITY_LIMIT);
+        drivetrain.nt_command_mode = true;
+        drivetrain.apply_nt_command(&table).unwrap();
+        let commanded = drivetrain.commanded_speeds;
+        assert_eq!(commanded.vx, 0.0);
+        assert!(commanded.vy > 0.0);
+
+        // Released sticks leave the bench command alone
+        drivetrain.set_input(Vector2::zeros(), 0.0).unwrap();
+        assert!(drivetrain.is_nt_command_mode());
+        assert_eq!(drivetrain.commanded_speeds, commanded);
+        assert!(modules[0].state().drive_target.abs() > 0.0);
+
+        drivetrain.set_input(Vector2::new(0.5, 0.0), 0.0).unwrap();
+        assert!(!drivetrain.is_nt_command_mode());
+
+        table.entries.borrow_mut().push(("omega", f64::NAN));
+        assert!(drivetrain.apply_nt_command(&table).is_err());
+    }
+
+    #[test]
+    fn datalog_records_each_drive_command() {
+        let path =
+            std::env::temp_dir().join(format!("swerve_drivetrain_{}.wpilog", std::process::id()));
+        let (mut drivetrain, _, modules) = sim();
+        for module in &modules {
+            module.update(|state| state.drive_current = 12.0);
+        }
+
+        // Each command logs the state the last one left behind
+        drivetrain.start_datalog(&path).unwrap();
+        for _ in 0..2 {
+            drivetrain
+                .set_chassis_speeds(ChassisSpeeds::new(1.0, 0.0, 0.0), false)
+                .unwrap();
+        }
+        drivetrain.stop_datalog().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(&bytes[..6], b"WPILOG");
+
+        // Entry ID and payload of every record after the header
+        let mut records = Vec::new();
+        let mut rest = &bytes[12..];
+        while !rest.is_empty() {
+            let entry = u32::from_le_bytes(rest[1..5].try_into().unwrap());
+            let size = u32::from_le_bytes(rest[5..9].try_into().unwrap()) as usize;
+            records.push((entry, rest[17..17 + size].to_vec()));
+            rest = &rest[17 + size..];
+        }
+
+        // Three entries started, then a sample of each per command
+        let entries = records.iter().map(|(entry, _)| *entry).collect::<Vec<_>>();
+        assert_eq!(entries, [0, 0, 0, 1, 2, 3, 1, 2, 3]);
+
+        let doubles = |payload: &[u8]| {
+            payload
+                .chunks(8)
+                .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(doubles(&records[3].1).len(), 3);
+        assert_eq!(doubles(&records[4].1), [0.0; 8]);
+        let states = doubles(&records[7].1);
+        assert!(states.iter().skip(1).step_by(2).all(|speed| *speed > 0.0));
+        assert_eq!(doubles(&records[8].1), [12.0; 4]);
+    }
+
+    /// Runs the self test to completion, moving each wheel by its measured speed as time passes
+    fn complete_self_test(drivetrain: &mut Drivetrain, modules: &[SimModule; 4]) -> SelfTestReport {
+        let mut test = pin!(drivetrain.run_self_test());
+        let mut last_step = Instant::now();
+
+        loop {
+            if let Poll::Ready(report) = poll_once(test.as_mut()) {
+                return report;
+            }
+
+            std::thread::sleep(Duration::from_millis(1));
+            for module in modules {
+                module.step(last_step.elapsed().as_secs_f32());
+            }
+            last_step = Instant::now();
+        }
+    }
+
+    #[test]
+    fn self_test_passes_healthy_modules_and_catches_an_inverted_drive() {
+        let (mut drivetrain, _, modules) = sim();
+        modules[1].update(|state| state.drive_inverted = true);
+
+        let report = complete_self_test(&mut drivetrain, &modules);
+
+        assert_eq!(report.modules[0], ModuleTestResult::Passed);
+        assert!(
+            matches!(report.modules[1], ModuleTestResult::Inverted(velocity) if velocity < 0.0),
+            "{report:?}"
+        );
+        assert_eq!(report.modules[2], ModuleTestResult::Skipped);
+        assert_eq!(report.modules[3], ModuleTestResult::Skipped);
+        assert!(!report.passed());
+
+        // Each module is stopped once its test is done
+        for module in &modules {
+            assert_eq!(module.state().drive_target, 0.0);
+        }
+    }
+
+    #[test]
+    fn self_test_fails_a_module_that_doesnt_steer() {
+        let (mut drivetrain, _, modules) = sim();
+        modules[0].update(|state| state.frozen = true);
+
+        let report = complete_self_test(&mut drivetrain, &modules);
+
+        assert!(
+            matches!(&report.modules[0], ModuleTestResult::Failed(reason) if reason.contains("steering")),
+            "{report:?}"
+        );
+        assert!(report.modules[1..]
+            .iter()
+            .all(|result| *result == ModuleTestResult::Skipped));
     }
 }
@@ -0,0 +1,368 @@
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use nalgebra::Vector2;
+use navx::NavX;
+use utils::tracing::warn;
+
+/// Degrees per second the two gyros of a `RedundantGyro` may disagree on rate before the primary
+/// is suspected
+const DIVERGENCE_RATE: f32 = 20.0;
+/// How long the rates must disagree before failing over, so one noisy sample doesn't
+const DIVERGENCE_TIME: Duration = Duration::from_millis(250);
+
+/// A yaw source for the drivetrain. Readings follow the NavX convention: degrees, clockwise
+/// positive.
+pub trait Gyro {
+    fn heading(&self) -> f32;
+    /// Degrees per second
+    fn rate(&self) -> f32;
+    /// The heading is meaningless until calibration finishes after power on
+    fn is_calibrating(&self) -> bool;
+    /// Whether readings are still arriving. Gyros that can't tell report connected.
+    fn is_connected(&self) -> bool {
+        true
+    }
+    /// Degrees, positive when the front of the robot is tilted up. Gyros that can't measure tilt
+    /// report level.
+    fn pitch(&self) -> f32 {
+        0.0
+    }
+    /// Degrees, positive when the left side of the robot is tilted up
+    fn roll(&self) -> f32 {
+        0.0
+    }
+    /// Horizontal linear acceleration in g with gravity removed, robot relative. Gyros without
+    /// an accelerometer report zero.
+    fn acceleration(&self) -> Vector2<f32> {
+        Vector2::zeros()
+    }
+}
+
+impl Gyro for NavX {
+    fn heading(&self) -> f32 {
+        NavX::heading(self)
+    }
+
+    fn rate(&self) -> f32 {
+        NavX::rate(self)
+    }
+
+    fn is_calibrating(&self) -> bool {
+        NavX::is_calibrating(self)
+    }
+
+    fn is_connected(&self) -> bool {
+        NavX::is_connected(self)
+    }
+
+    fn pitch(&self) -> f32 {
+        NavX::pitch(self)
+    }
+
+    fn roll(&self) -> f32 {
+        NavX::roll(self)
+    }
+
+    fn acceleration(&self) -> Vector2<f32> {
+        Vector2::new(
+            NavX::world_linear_accel_x(self),
+            NavX::world_linear_accel_y(self),
+        )
+    }
+}
+
+/// A gyro whose readings are set programmatically, for running drivetrain logic without a NavX.
+/// Clones share the same readings.
+#[derive(Clone, Default)]
+pub struct FakeGyro {
+    heading: Rc<Cell<f32>>,
+    rate: Rc<Cell<f32>>,
+    calibrating: Rc<Cell<bool>>,
+    disconnected: Rc<Cell<bool>>,
+    pitch: Rc<Cell<f32>>,
+    roll: Rc<Cell<f32>>,
+    acceleration: Rc<Cell<Vector2<f32>>>,
+}
+
+impl FakeGyro {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_heading(&self, degrees: f32) {
+        self.heading.set(degrees);
+    }
+
+    pub fn set_rate(&self, degrees_per_second: f32) {
+        self.rate.set(degrees_per_second);
+    }
+
+    pub fn set_calibrating(&self, calibrating: bool) {
+        self.calibrating.set(calibrating);
+    }
+
+    pub fn set_connected(&self, connected: bool) {
+        self.disconnected.set(!connected);
+    }
+
+    pub fn set_pitch(&self, degrees: f32) {
+        self.pitch.set(degrees);
+    }
+
+    pub fn set_roll(&self, degrees: f32) {
+        self.roll.set(degrees);
+    }
+
+    pub fn set_acceleration(&self, g: Vector2<f32>) {
+        self.acceleration.set(g);
+    }
+}
+
+impl Gyro for FakeGyro {
+    fn heading(&self) -> f32 {
+        self.heading.get()
+    }
+
+    fn rate(&self) -> f32 {
+        self.rate.get()
+    }
+
+    fn is_calibrating(&self) -> bool {
+        self.calibrating.get()
+    }
+
+    fn is_connected(&self) -> bool {
+        !self.disconnected.get()
+    }
+
+    fn pitch(&self) -> f32 {
+        self.pitch.get()
+    }
+
+    fn roll(&self) -> f32 {
+        self.roll.get()
+    }
+
+    fn acceleration(&self) -> Vector2<f32> {
+        self.acceleration.get()
+    }
+}
+
+/// A primary gyro backed by a secondary one. Readings come from the primary until it disconnects
+/// or its rate disagrees with the secondary's for too long, then permanently from the secondary,
+/// since a gyro that drops out usually comes back with its yaw reset. A disconnected secondary
+/// is never failed over to, since it reads zero rather than the robot's motion. While the primary is
+/// healthy the difference between the two headings is tracked, so the heading carries on from
+/// where the primary left off instead of jumping.
+pub struct RedundantGyro<P, S> {
+    primary: P,
+    secondary: S,
+    /// Degrees added to the secondary heading to line it up with the primary
+    secondary_offset: Cell<f32>,
+    failed_over: Cell<bool>,
+    /// Degrees per second
+    divergence_rate: f32,
+    divergence_time: Duration,
+    diverging_since: Cell<Option<Instant>>,
+    /// Whether the secondary being down has been logged since it was last connected
+    warned_secondary_down: Cell<bool>,
+}
+
+impl<P: Gyro, S: Gyro> RedundantGyro<P, S> {
+    pub fn new(primary: P, secondary: S) -> Self {
+        Self {
+            primary,
+            secondary,
+            secondary_offset: Cell::new(0.0),
+            failed_over: Cell::new(false),
+            divergence_rate: DIVERGENCE_RATE,
+            divergence_time: DIVERGENCE_TIME,
+            diverging_since: Cell::new(None),
+            warned_secondary_down: Cell::new(false),
+        }
+    }
+
+    /// Fails over once the rates differ by more than `degrees_per_second` for `duration`. A
+    /// primary that is drifting or stuck still reports connected, so this catches what the
+    /// disconnect check can't.
+    pub fn set_divergence_limit(&mut self, degrees_per_second: f32, duration: Duration) {
+        self.divergence_rate = degrees_per_second;
+        self.divergence_time = duration;
+        self.diverging_since.set(None);
+    }
+
+    /// Whether readings have switched to the secondary
+    pub fn failed_over(&self) -> bool {
+        self.failed_over.get()
+    }
+
+    fn primary_healthy(&self) -> bool {
+        if self.failed_over.get() {
+            return false;
+        }
+
+        if !self.secondary.is_connected() {
+            self.diverging_since.set(None);
+
+            if !self.warned_secondary_down.replace(true) {
+                warn!("Secondary gyro disconnected, staying on the primary without a backup");
+            }
+
+            return true;
+        }
+
+        self.warned_secondary_down.set(false);
+
+        if !self.primary.is_connected() {
+            warn!("Primary gyro disconnected, failing over to the secondary");
+            self.failed_over.set(true);
+            return false;
+        }
+
+        let divergence = (self.primary.rate() - self.secondary.rate()).abs();
+
+        if divergence <= self.divergence_rate {
+            self.diverging_since.set(None);
+        } else {
+            let since = self.diverging_since.get().unwrap_or_else(Instant::now);
+            self.diverging_since.set(Some(since));
+
+            if since.elapsed() >= self.divergence_time {
+                warn!("Gyro rates differ by {divergence:.1} deg/s, failing over to the secondary");
+                self.failed_over.set(true);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn active(&self) -> &dyn Gyro {
+        if self.primary_healthy() {
+            &self.primary
+        } else {
+            &self.secondary
+        }
+    }
+}
+
+impl<P: Gyro, S: Gyro> Gyro for RedundantGyro<P, S> {
+    fn heading(&self) -> f32 {
+        if self.primary_healthy() {
+            let heading = self.primary.heading();
+            self.secondary_offset
+                .set(heading - self.secondary.heading());
+
+            heading
+        } else {
+            self.secondary.heading() + self.secondary_offset.get()
+        }
+    }
+
+    fn rate(&self) -> f32 {
+        self.active().rate()
+    }
+
+    fn is_calibrating(&self) -> bool {
+        self.active().is_calibrating()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.active().is_connected()
+    }
+
+    fn pitch(&self) -> f32 {
+        self.active().pitch()
+    }
+
+    fn roll(&self) -> f32 {
+        self.active().roll()
+    }
+
+    fn acceleration(&self) -> Vector2<f32> {
+        self.active().acceleration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fails_over_on_sustained_divergence() {
+        let primary = FakeGyro::new();
+        let secondary = FakeGyro::new();
+        let mut gyro = RedundantGyro::new(primary.clone(), secondary.clone());
+
+        primary.set_heading(90.0);
+        secondary.set_heading(10.0);
+        assert_eq!(gyro.heading(), 90.0);
+
+        // A brief disagreement is tolerated
+        gyro.set_divergence_limit(5.0, Duration::from_secs(3600));
+        primary.set_rate(30.0);
+        assert_eq!(gyro.rate(), 30.0);
+        assert!(!gyro.failed_over());
+
+        primary.set_rate(0.0);
+        assert_eq!(gyro.rate(), 0.0);
+
+        // The primary keeps spinning while the secondary says the robot is still
+        gyro.set_divergence_limit(5.0, Duration::ZERO);
+        primary.set_rate(30.0);
+        assert_eq!(gyro.rate(), 0.0);
+        assert!(gyro.failed_over());
+
+        // The heading carries on from the primary and then follows the secondary
+        primary.set_rate(0.0);
+        primary.set_heading(200.0);
+        secondary.set_heading(15.0);
+        assert_eq!(gyro.heading(), 95.0);
+    }
+
+    #[test]
+    fn fails_over_on_disconnect() {
+        let primary = FakeGyro::new();
+        let secondary = FakeGyro::new();
+        let gyro = RedundantGyro::new(primary.clone(), secondary.clone());
+
+        primary.set_rate(5.0);
+        secondary.set_rate(5.0);
+        assert!(!gyro.failed_over());
+
+        primary.set_connected(false);
+        secondary.set_rate(7.0);
+        assert_eq!(gyro.rate(), 7.0);
+        assert!(gyro.failed_over());
+    }
+
+    #[test]
+    fn never_fails_over_to_a_disconnected_secondary() {
+        let primary = FakeGyro::new();
+        let secondary = FakeGyro::new();
+        let mut gyro = RedundantGyro::new(primary.clone(), secondary.clone());
+        gyro.set_divergence_limit(5.0, Duration::ZERO);
+
+        // The robot turns while the unplugged secondary reads still
+        secondary.set_connected(false);
+        primary.set_rate(90.0);
+        primary.set_heading(45.0);
+        assert_eq!(gyro.rate(), 90.0);
+        assert_eq!(gyro.heading(), 45.0);
+        assert!(!gyro.failed_over());
+
+        // With both down there is nothing better than the primary
+        primary.set_connected(false);
+        assert_eq!(gyro.rate(), 90.0);
+        assert!(!gyro.failed_over());
+
+        secondary.set_connected(true);
+        secondary.set_rate(80.0);
+        assert_eq!(gyro.rate(), 80.0);
+        assert!(gyro.failed_over());
+    }
+}
@@ -1,17 +1,301 @@
+use std::pin::pin;
+
+use anyhow::ensure;
 use drivetrain::Drivetrain;
+use futures::future::{select, Either};
 use nalgebra::Vector2;
 use robotrs::{
-    hid::controller::XboxController, robot::AsyncRobot, scheduler::guard, yield_now, Deadzone,
-    FailableDefault,
+    hid::controller::XboxController,
+    robot::AsyncRobot,
+    scheduler::{guard, spawn},
+    yield_now, Deadzone, FailableDefault,
 };
-use utils::{periodic, subsystem::Subsystem, tracing::info, trigger::TriggerExt, wait};
+use util::{radial_deadband, OnDrop, ResponseCurve};
+use utils::{error::log, periodic, subsystem::Subsystem, tracing::info, trigger::TriggerExt, wait};
 
+pub mod controller;
+pub mod datalog;
 pub mod drivetrain;
+pub mod gyro;
+pub mod module_io;
+pub mod nt;
+pub mod offsets;
+pub mod profile;
+pub mod replay;
+pub mod saved_pose;
+pub mod self_test;
+pub mod sim;
+pub mod slew;
 pub mod swerve_module;
+pub mod telemetry;
+pub mod trajectory;
+pub mod units;
+pub mod util;
+
+/// Fraction of full speed the d-pad drives at
+const POV_SNAP_SPEED: f32 = 0.2;
+
+/// How one controller axis maps onto a drive input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AxisMapping {
+    inverted: bool,
+    /// Multiplier on the raw axis
+    scale: f32,
+}
+
+impl AxisMapping {
+    /// Flipped, at full scale, the default for every axis
+    pub const INVERTED: AxisMapping = AxisMapping {
+        inverted: true,
+        scale: 1.0,
+    };
+
+    /// Flips the axis with `inverted`, after multiplying it by `scale`, which must be positive
+    pub fn new(inverted: bool, scale: f32) -> anyhow::Result<Self> {
+        ensure!(
+            scale > 0.0 && scale.is_finite(),
+            "Axis scale must be positive, got {scale}"
+        );
+
+        Ok(Self { inverted, scale })
+    }
+
+    fn apply(self, value: f32) -> f32 {
+        let value = value * self.scale;
+
+        if self.inverted {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
+/// Controller axis mappings for the driver. The defaults turn the controller's down and right
+/// positive axes into forward, left and counterclockwise positive inputs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AxisConfig {
+    /// Left stick y to field forward
+    pub forward: AxisMapping,
+    /// Left stick x to field left
+    pub strafe: AxisMapping,
+    /// Right stick x to counterclockwise turn rate. Trigger rotation input isn't affected.
+    pub turn: AxisMapping,
+}
+
+impl AxisConfig {
+    pub const fn new(forward: AxisMapping, strafe: AxisMapping, turn: AxisMapping) -> Self {
+        Self {
+            forward,
+            strafe,
+            turn,
+        }
+    }
+}
+
+impl Default for AxisConfig {
+    fn default() -> Self {
+        Self {
+            forward: AxisMapping::INVERTED,
+            strafe: AxisMapping::INVERTED,
+            turn: AxisMapping::INVERTED,
+        }
+    }
+}
+
+/// Meters each d-pad press moves the robot while nudging
+const NUDGE_DISTANCE: f32 = 0.05;
+
+/// Where the driver's turn rate comes from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RotationInput {
+    #[default]
+    RightStick,
+    /// Left trigger turns counterclockwise, right trigger clockwise
+    Triggers,
+}
+
+/// One loop of raw driver controller readings. The default is a controller at rest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriverSticks {
+    pub left_x: f32,
+    pub left_y: f32,
+    pub right_x: f32,
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+    /// Degrees clockwise from up, -1 when released
+    pub pov: i32,
+}
+
+impl Default for DriverSticks {
+    fn default() -> Self {
+        Self {
+            left_x: 0.0,
+            left_y: 0.0,
+            right_x: 0.0,
+            left_trigger: 0.0,
+            right_trigger: 0.0,
+            pov: -1,
+        }
+    }
+}
+
+impl DriverSticks {
+    fn read(controller: &XboxController) -> anyhow::Result<Self> {
+        Ok(Self {
+            left_x: controller.left_x()?,
+            left_y: controller.left_y()?,
+            right_x: controller.right_x()?,
+            left_trigger: controller.left_trigger()?,
+            right_trigger: controller.right_trigger()?,
+            pov: controller.pov()?,
+        })
+    }
+}
+
+/// How the driver's controller readings become drive inputs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriverInput {
+    rotation_input: RotationInput,
+    axis_config: AxisConfig,
+    pov_snap_speed: f32,
+    /// Shapes the translation stick magnitude after the deadband
+    translation_curve: ResponseCurve,
+    /// Shapes the turn input after its deadzone
+    turn_curve: ResponseCurve,
+}
+
+impl Default for DriverInput {
+    fn default() -> Self {
+        Self {
+            rotation_input: RotationInput::default(),
+            axis_config: AxisConfig::default(),
+            pov_snap_speed: POV_SNAP_SPEED,
+            translation_curve: ResponseCurve::default(),
+            turn_curve: ResponseCurve::default(),
+        }
+    }
+}
+
+impl DriverInput {
+    /// Maps the controller axes onto drive inputs, e.g. to flip an axis for one driver
+    pub fn set_axis_config(&mut self, axis_config: AxisConfig) {
+        self.axis_config = axis_config;
+    }
+
+    /// Shapes the translation stick magnitude, e.g. for finer control near center
+    pub fn set_translation_curve(&mut self, curve: ResponseCurve) {
+        self.translation_curve = curve;
+    }
+
+    /// Shapes the turn input
+    pub fn set_turn_curve(&mut self, curve: ResponseCurve) {
+        self.turn_curve = curve;
+    }
+
+    /// Normalized field relative translation, with `deadband` as the radius of the translation
+    /// deadband. While the d-pad is held it overrides the stick with a fixed slow speed in that
+    /// exact direction, for final alignment.
+    pub fn translation(&self, sticks: DriverSticks, deadband: f32) -> Vector2<f32> {
+        if let Some(direction) = pov_direction(sticks.pov) {
+            return direction * self.pov_snap_speed;
+        }
+
+        let drive = radial_deadband(
+            Vector2::new(
+                self.axis_config.forward.apply(sticks.left_y),
+                self.axis_config.strafe.apply(sticks.left_x),
+            ),
+            deadband,
+        );
+
+        self.translation_curve.apply_vector(drive)
+    }
+
+    /// Normalized counterclockwise turn rate
+    pub fn turn(&self, sticks: DriverSticks) -> f32 {
+        let turn = match self.rotation_input {
+            RotationInput::RightStick => self.axis_config.turn.apply(sticks.right_x.deadzone(0.1)),
+            RotationInput::Triggers => {
+                sticks.left_trigger.deadzone(0.1) - sticks.right_trigger.deadzone(0.1)
+            }
+        };
+
+        self.turn_curve.apply(turn)
+    }
+
+    /// Whether the readings would drive the robot in teleop
+    pub fn is_active(&self, sticks: DriverSticks, deadband: f32) -> bool {
+        self.translation(sticks, deadband) != Vector2::zeros() || self.turn(sticks) != 0.0
+    }
+}
 
 pub struct Robot {
     drivetrain: Subsystem<Drivetrain>,
     controller: XboxController,
+    input: DriverInput,
+}
+
+impl Robot {
+    /// Replaces how the driver's controller readings become drive inputs, e.g. to load a
+    /// driver's preferred response curves
+    pub fn set_driver_input(&mut self, input: DriverInput) {
+        self.input = input;
+    }
+
+    /// See `DriverInput::translation`
+    fn translation_input(&self, deadband: f32) -> anyhow::Result<Vector2<f32>> {
+        Ok(self
+            .input
+            .translation(DriverSticks::read(&self.controller)?, deadband))
+    }
+
+    /// Nudges the robot a few centimeters in the field direction of each d-pad press, waiting for
+    /// the d-pad to be released between nudges
+    async fn nudge_with_pov(&self, drivetrain: &mut Drivetrain) -> anyhow::Result<()> {
+        loop {
+            if let Some(direction) = pov_direction(self.controller.pov()?) {
+                drivetrain.nudge(direction * NUDGE_DISTANCE).await?;
+
+                while pov_direction(self.controller.pov()?).is_some() {
+                    yield_now().await;
+                }
+            }
+
+            yield_now().await;
+        }
+    }
+
+    /// Resolves once the driver gives any input teleop would drive with, including the d-pad
+    /// and triggers, using the same mappings and deadbands
+    async fn wait_for_manual_input(&self, deadband: f32) -> anyhow::Result<()> {
+        loop {
+            if self
+                .input
+                .is_active(DriverSticks::read(&self.controller)?, deadband)
+            {
+                return Ok(());
+            }
+
+            yield_now().await;
+        }
+    }
+
+    fn turn_input(&self) -> anyhow::Result<f32> {
+        Ok(self.input.turn(DriverSticks::read(&self.controller)?))
+    }
+}
+
+/// Unit field vector for one of the 8 d-pad directions, given in degrees clockwise from up with
+/// -1 when released. Up is field forward (+x) and left is +y.
+fn pov_direction(pov: i32) -> Option<Vector2<f32>> {
+    if !(0..360).contains(&pov) || pov % 45 != 0 {
+        return None;
+    }
+
+    let angle = -(pov as f32).to_radians();
+
+    Some(Vector2::new(angle.cos(), angle.sin()))
 }
 
 impl AsyncRobot for Robot {
@@ -20,19 +304,40 @@ impl AsyncRobot for Robot {
     }
 
     async fn get_enabled_future(&'static self) -> anyhow::Result<()> {
+        self.drivetrain.lock(1).await.set_enabled(true)?;
+
+        // The scheduler drops this future on disable
+        let _disable = OnDrop::new(move || {
+            spawn(async move {
+                let _ = log(async { self.drivetrain.lock(1).await.set_enabled(false) }).await;
+            })
+            .detach();
+        });
+
+        // Fails enable rather than letting the robot drive with steering that takes the long way
+        // around. The emergency stop keeps it still until the code restarts.
+        let mut drivetrain = self.drivetrain.lock(1).await;
+        if let Err(err) = drivetrain.check_turn_wrapping().await {
+            drivetrain.emergency_stop();
+            return Err(err);
+        }
+        drop(drivetrain);
+
+        wait!();
+
         Ok(())
     }
 
     async fn get_teleop_future(&'static self) -> anyhow::Result<()> {
+        self.drivetrain.lock(1).await.reset_slew_limiters();
+
         periodic!([drivetrain = self.drivetrain => 1], async {
+            let drive = self
+                .translation_input(drivetrain.translation_deadband())
+                .unwrap();
+
             drivetrain
-                .set_input(
-                    Vector2::new(
-                        -self.controller.left_y().unwrap().deadzone(0.1),
-                        -self.controller.left_x().unwrap().deadzone(0.1),
-                    ),
-                    -self.controller.right_x().unwrap().deadzone(0.1),
-                )
+                .set_input(drive, self.turn_input().unwrap())
                 .unwrap();
 
             yield_now().await;
@@ -52,6 +357,32 @@ impl AsyncRobot for Robot {
             anyhow::Ok(())
         });
 
+        // Holding Y drives back to the start pose, until released or either stick is moved
+        self.controller.y().while_pressed(move || async move {
+            let mut drivetrain = self.drivetrain.lock(2).await;
+            let deadband = drivetrain.translation_deadband();
+
+            let returning = pin!(drivetrain.return_to_start());
+            let manual = pin!(self.wait_for_manual_input(deadband));
+
+            match select(returning, manual).await {
+                Either::Left((result, _)) => result,
+                Either::Right((result, _)) => {
+                    info!("Manual input, cancelling return to start");
+                    result
+                }
+            }
+        });
+
+        // Holding the left bumper turns the d-pad from slow driving into precise nudges
+        self.controller
+            .left_bumper()
+            .while_pressed(move || async move {
+                let mut drivetrain = self.drivetrain.lock(2).await;
+
+                self.nudge_with_pov(&mut drivetrain).await
+            });
+
         Ok(())
     }
 }
@@ -61,6 +392,118 @@ impl FailableDefault for Robot {
         Ok(Self {
             drivetrain: Subsystem::new(Drivetrain::failable_default()?),
             controller: XboxController::new(0)?,
+            input: DriverInput::default(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_input_matches_what_teleop_drives_with() {
+        let input = DriverInput::default();
+        let still = DriverSticks::default();
+
+        assert!(!input.is_active(still, 0.1));
+        assert!(!input.is_active(
+            DriverSticks {
+                left_y: -0.05,
+                ..still
+            },
+            0.1
+        ));
+        assert!(!input.is_active(
+            DriverSticks {
+                right_x: 0.05,
+                ..still
+            },
+            0.1
+        ));
+
+        assert!(input.is_active(
+            DriverSticks {
+                left_y: -0.5,
+                ..still
+            },
+            0.1
+        ));
+        assert!(input.is_active(
+            DriverSticks {
+                right_x: 0.5,
+                ..still
+            },
+            0.1
+        ));
+        assert!(input.is_active(DriverSticks { pov: 90, ..still }, 0.1));
+
+        // A wider deadband swallows what a narrow one lets through
+        assert!(!input.is_active(
+            DriverSticks {
+                left_y: -0.3,
+                ..still
+            },
+            0.4
+        ));
+
+        // Triggers only count when they are the rotation input
+        let pulled = DriverSticks {
+            left_trigger: 0.8,
+            ..still
+        };
+        assert!(!input.is_active(pulled, 0.1));
+        let triggers = DriverInput {
+            rotation_input: RotationInput::Triggers,
+            ..DriverInput::default()
+        };
+        assert!(triggers.is_active(pulled, 0.1));
+        assert!(triggers.turn(pulled) > 0.0);
+    }
+
+    #[test]
+    fn response_curves_shape_the_driver_input() {
+        let mut input = DriverInput::default();
+        let curve = ResponseCurve::new(0.5, 0.4).unwrap();
+        input.set_translation_curve(curve);
+        input.set_turn_curve(curve);
+
+        let sticks = DriverSticks {
+            left_y: -0.5,
+            right_x: -1.0,
+            ..DriverSticks::default()
+        };
+
+        assert!((input.translation(sticks, 0.0).x - 0.2).abs() < 1e-6);
+        assert!((input.turn(sticks) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn axis_mappings_flip_and_scale_each_axis() {
+        let mut input = DriverInput::default();
+        let sticks = DriverSticks {
+            left_y: -0.5,
+            left_x: 0.25,
+            right_x: 0.5,
+            ..DriverSticks::default()
+        };
+
+        // Up, right and right turn on the controller are forward, right and clockwise
+        let translation = input.translation(sticks, 0.0);
+        assert!((translation - Vector2::new(0.5, -0.25)).norm() < 1e-6);
+        assert!(input.turn(sticks) < 0.0);
+
+        input.set_axis_config(AxisConfig::new(
+            AxisMapping::new(false, 0.5).unwrap(),
+            AxisMapping::INVERTED,
+            AxisMapping::new(false, 1.0).unwrap(),
+        ));
+        let translation = input.translation(sticks, 0.0);
+        assert!((translation - Vector2::new(-0.25, -0.25)).norm() < 1e-6);
+        assert!(input.turn(sticks) > 0.0);
+
+        assert!(AxisMapping::new(true, 0.0).is_err());
+        assert!(AxisMapping::new(true, -1.0).is_err());
+        assert!(AxisMapping::new(false, f32::NAN).is_err());
+    }
+}
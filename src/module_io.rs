@@ -0,0 +1,226 @@
+use revlib::{encoder::Encoder, SparkMax};
+use robotrs::{
+    control::ControlSafe,
+    motor::{IdleMode, SetIdleMode},
+};
+use std::f32::consts::PI;
+
+use crate::swerve_module::{DriveControlMode, ModuleGains};
+
+const WHEEL_DIAMETER: f32 = 3.0; // inches
+
+const TURN_POSITION_CONVERSION_FACTOR: f32 = 2.0 * PI;
+// rotations to radians
+const TURN_VELOCITY_CONVERSION_FACTOR: f32 = TURN_POSITION_CONVERSION_FACTOR / 60.0;
+// radians per minute to radians per second
+const DRIVE_POSITION_CONVERSION_FACTOR: f32 = (WHEEL_DIAMETER * PI) / (4.71 * 39.3701);
+// ^ 4.71 is gear reduction, 39.3701 is inches per meter
+const DRIVE_VELOCITY_CONVERSION_FACTOR: f32 = DRIVE_POSITION_CONVERSION_FACTOR / 60.0;
+// meter per minute to meter per second
+
+const TURN_MAX_OUTPUT: f32 = 1.0;
+const TURN_MIN_OUTPUT: f32 = -1.0;
+const DRIVE_MAX_OUTPUT: f32 = 1.0;
+const DRIVE_MIN_OUTPUT: f32 = -1.0;
+
+const TURN_MAX_CURRENT: u8 = 20; // amps
+const DRIVE_MAX_CURRENT: u8 = 50;
+
+const TURN_IDLE_MODE: IdleMode = IdleMode::Brake;
+const DRIVE_IDLE_MODE: IdleMode = IdleMode::Brake;
+
+/// Reads the raw turn angle in radians and the drive position in meters
+pub type PositionReader = Box<dyn FnMut() -> anyhow::Result<(f32, f32)>>;
+
+/// The motors and sensors behind one swerve module. `SwerveModule` does the steering logic on
+/// top of this, so it runs the same against the real motor controllers or a simulation.
+///
+/// Turn angles are raw absolute encoder radians, without the module offset. Drive values are
+/// meters and meters per second.
+pub trait ModuleIo {
+    /// Closed loop steering to a raw angle in radians
+    fn set_turn_position(&mut self, radians: f32) -> anyhow::Result<()>;
+    /// Open loop turn motor voltage
+    fn set_turn_voltage(&mut self, volts: f32) -> anyhow::Result<()>;
+    fn stop_turn(&mut self);
+    /// Closed loop drive target, meters per second or amps depending on `mode`
+    fn set_drive(&mut self, value: f32, mode: DriveControlMode) -> anyhow::Result<()>;
+    fn stop_drive(&mut self);
+    /// Lets the drive motor coast when not driven, or restores braking
+    fn set_drive_coast(&mut self, coast: bool) -> anyhow::Result<()>;
+    fn set_drive_current_limit(&mut self, amps: u8) -> anyhow::Result<()>;
+    fn set_turn_current_limit(&mut self, amps: u8) -> anyhow::Result<()>;
+
+    /// Raw absolute encoder angle in radians
+    fn turn_angle(&mut self) -> anyhow::Result<f32>;
+    /// Radians per second
+    fn turn_velocity(&mut self) -> anyhow::Result<f32>;
+    /// Meters
+    fn drive_position(&mut self) -> anyhow::Result<f32>;
+    fn set_drive_position(&mut self, meters: f32) -> anyhow::Result<()>;
+    /// Meters per second
+    fn drive_velocity(&mut self) -> anyhow::Result<f32>;
+    /// Amps
+    fn drive_current(&mut self) -> anyhow::Result<f32>;
+    /// Drive and turn applied output as a fraction of bus voltage
+    fn applied_outputs(&mut self) -> anyhow::Result<(f32, f32)>;
+
+    /// A reader of the raw turn angle and drive position that doesn't borrow the module, for the
+    /// odometry task
+    fn position_reader(&mut self) -> anyhow::Result<PositionReader>;
+}
+
+/// A module driven by two SPARK MAX controllers, a NEO drive and a NEO 550 turn motor with a
+/// through bore absolute encoder
+pub struct SparkMaxModule {
+    turn: SparkMax,
+    drive: SparkMax,
+}
+
+impl SparkMaxModule {
+    /// Resets and configures both controllers
+    pub fn new(drive_id: i32, turn_id: i32, gains: ModuleGains) -> anyhow::Result<Self> {
+        let mut turn = SparkMax::new(turn_id, revlib::MotorType::Brushless)?;
+        let mut drive = SparkMax::new(drive_id, revlib::MotorType::Brushless)?;
+
+        turn.reset_settings()?;
+        drive.reset_settings()?;
+
+        turn.set_pid_range(TURN_MIN_OUTPUT..=TURN_MAX_OUTPUT)?;
+        drive.set_pid_range(DRIVE_MIN_OUTPUT..=DRIVE_MAX_OUTPUT)?;
+
+        let mut turn_encoder = turn.get_absolute_encoder()?;
+        let mut drive_encoder = drive.get_relative_encoder()?;
+
+        turn_encoder.set_inverted(true)?;
+
+        turn_encoder.set_position_conversion_factor(TURN_POSITION_CONVERSION_FACTOR)?;
+        turn_encoder.set_velocity_conversion_factor(TURN_VELOCITY_CONVERSION_FACTOR)?;
+        drive_encoder.set_position_conversion_factor(DRIVE_POSITION_CONVERSION_FACTOR)?;
+        drive_encoder.set_velocity_conversion_factor(DRIVE_VELOCITY_CONVERSION_FACTOR)?;
+
+        turn.set_pid(gains.turn.p, gains.turn.d, gains.turn.i, gains.turn.f)?;
+        drive.set_pid(gains.drive.p, gains.drive.d, gains.drive.i, gains.drive.f)?;
+
+        turn.set_pid_range(-1.0..=1.0)?;
+        drive.set_pid_range(-1.0..=1.0)?;
+
+        turn.set_wrapping(true, 0.0, 2.0 * PI)?;
+
+        turn.set_pid_input(&turn_encoder)?;
+        drive.set_pid_input(&drive_encoder)?;
+
+        turn.set_smart_current_limit(TURN_MAX_CURRENT)?;
+        drive.set_smart_current_limit(DRIVE_MAX_CURRENT)?;
+
+        turn.set_idle_mode(TURN_IDLE_MODE)?;
+        drive.set_idle_mode(DRIVE_IDLE_MODE)?;
+
+        turn.write_settings()?;
+        drive.write_settings()?;
+
+        Ok(Self { turn, drive })
+    }
+}
+
+impl ModuleIo for SparkMaxModule {
+    fn set_turn_position(&mut self, radians: f32) -> anyhow::Result<()> {
+        self.turn
+            .set_reference(radians, revlib::ControlType::Position)?;
+
+        Ok(())
+    }
+
+    fn set_turn_voltage(&mut self, volts: f32) -> anyhow::Result<()> {
+        self.turn
+            .set_reference(volts, revlib::ControlType::Voltage)?;
+
+        Ok(())
+    }
+
+    fn stop_turn(&mut self) {
+        self.turn.stop();
+    }
+
+    fn set_drive(&mut self, value: f32, mode: DriveControlMode) -> anyhow::Result<()> {
+        let control_type = match mode {
+            DriveControlMode::Velocity => revlib::ControlType::Velocity,
+            DriveControlMode::Current => revlib::ControlType::Current,
+        };
+
+        self.drive.set_reference(value, control_type)?;
+
+        Ok(())
+    }
+
+    fn stop_drive(&mut self) {
+        self.drive.stop();
+    }
+
+    fn set_drive_coast(&mut self, coast: bool) -> anyhow::Result<()> {
+        self.drive.set_idle_mode(if coast {
+            IdleMode::Coast
+        } else {
+            DRIVE_IDLE_MODE
+        })?;
+
+        Ok(())
+    }
+
+    fn set_drive_current_limit(&mut self, amps: u8) -> anyhow::Result<()> {
+        set_current_limit(&mut self.drive, amps)
+    }
+
+    fn set_turn_current_limit(&mut self, amps: u8) -> anyhow::Result<()> {
+        set_current_limit(&mut self.turn, amps)
+    }
+
+    fn turn_angle(&mut self) -> anyhow::Result<f32> {
+        Ok(self.turn.get_absolute_encoder()?.get_position()?)
+    }
+
+    fn turn_velocity(&mut self) -> anyhow::Result<f32> {
+        Ok(self.turn.get_absolute_encoder()?.get_velocity()?)
+    }
+
+    fn drive_position(&mut self) -> anyhow::Result<f32> {
+        Ok(self.drive.get_relative_encoder()?.get_position()?)
+    }
+
+    fn set_drive_position(&mut self, meters: f32) -> anyhow::Result<()> {
+        self.drive.get_relative_encoder()?.set_position(meters)?;
+
+        Ok(())
+    }
+
+    fn drive_velocity(&mut self) -> anyhow::Result<f32> {
+        Ok(self.drive.get_relative_encoder()?.get_velocity()?)
+    }
+
+    fn drive_current(&mut self) -> anyhow::Result<f32> {
+        Ok(self.drive.get_output_current()?)
+    }
+
+    fn applied_outputs(&mut self) -> anyhow::Result<(f32, f32)> {
+        Ok((
+            self.drive.get_applied_output()?,
+            self.turn.get_applied_output()?,
+        ))
+    }
+
+    fn position_reader(&mut self) -> anyhow::Result<PositionReader> {
+        let mut turn_encoder = self.turn.get_absolute_encoder()?;
+        let mut drive_encoder = self.drive.get_relative_encoder()?;
+
+        Ok(Box::new(move || {
+            Ok((turn_encoder.get_position()?, drive_encoder.get_position()?))
+        }))
+    }
+}
+
+fn set_current_limit(motor: &mut SparkMax, amps: u8) -> anyhow::Result<()> {
+    motor.set_smart_current_limit(amps)?;
+    motor.write_settings()?;
+
+    Ok(())
+}
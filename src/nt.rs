@@ -0,0 +1,7 @@
+/// The slice of a NetworkTables client the drivetrain needs, so it can publish and read values
+/// without depending on a particular client
+pub trait NetworkTable {
+    fn set_f64_array(&self, key: &str, value: &[f64]) -> anyhow::Result<()>;
+    /// `None` if the key hasn't been published
+    fn get_f64(&self, key: &str) -> Option<f64>;
+}
@@ -0,0 +1,126 @@
+use std::{f32::consts::PI, fs, io::ErrorKind, path::Path};
+
+use anyhow::{anyhow, ensure};
+
+use crate::drivetrain::MODULE_NAMES;
+
+/// Where calibrated module offsets are persisted on the roboRIO
+pub const OFFSETS_PATH: &str = "/home/lvuser/swerve_offsets.toml";
+
+/// Radians a saved offset may differ from the value that was written when it is read back
+const SAVE_TOLERANCE: f32 = 1e-6;
+
+/// Radians, in module order: front left, front right, rear left, rear right
+pub const DEFAULT_OFFSETS: [f32; 4] = [-PI / 2.0, 0.0, PI, PI / 2.0];
+
+/// The key a module's offset is stored under, e.g. `front_left`
+fn offset_key(name: &str) -> String {
+    name.replace(' ', "_")
+}
+
+/// Reads module offsets stored as one `front_left = <radians>` line per module, in any order.
+/// Blank lines and `#` comments are ignored. Returns `None` if the file doesn't exist.
+pub fn load_offsets(path: impl AsRef<Path>) -> anyhow::Result<Option<[f32; 4]>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut offsets = [None; 4];
+
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected `module = offset`, found {line:?}"))?;
+        let key = key.trim();
+
+        let index = MODULE_NAMES
+            .iter()
+            .position(|name| offset_key(name) == key)
+            .ok_or_else(|| anyhow!("Unknown module {key:?} in offsets file"))?;
+        ensure!(
+            offsets[index].is_none(),
+            "Module {key:?} appears more than once in offsets file"
+        );
+
+        let offset = value.trim().parse::<f32>()?;
+        ensure!(offset.is_finite(), "Module offsets must be finite");
+
+        offsets[index] = Some(offset);
+    }
+
+    let mut loaded = [0.0; 4];
+
+    for ((loaded, offset), name) in loaded.iter_mut().zip(offsets).zip(MODULE_NAMES) {
+        *loaded = offset.ok_or_else(|| anyhow!("Missing the {name} module offset"))?;
+    }
+
+    Ok(Some(loaded))
+}
+
+/// Writes module offsets in the format `load_offsets` reads, then reads the file back to check
+/// it holds the same values
+pub fn save_offsets(path: impl AsRef<Path>, offsets: [f32; 4]) -> anyhow::Result<()> {
+    let path = path.as_ref();
+
+    ensure!(
+        offsets.iter().all(|offset| offset.is_finite()),
+        "Module offsets must be finite"
+    );
+
+    let contents = MODULE_NAMES
+        .iter()
+        .zip(offsets)
+        .map(|(name, offset)| format!("{} = {offset}\n", offset_key(name)))
+        .collect::<String>();
+    fs::write(path, contents)?;
+
+    let saved = load_offsets(path)?.ok_or_else(|| anyhow!("Saved offsets file disappeared"))?;
+    ensure!(
+        saved
+            .iter()
+            .zip(offsets)
+            .all(|(saved, offset)| (saved - offset).abs() <= SAVE_TOLERANCE),
+        "Saved offsets {saved:?} do not match {offsets:?}"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn offsets_round_trip_by_module_name() {
+        let path = env::temp_dir().join(format!("swerve_offsets_{}.toml", std::process::id()));
+        let offsets = [0.25, -1.5, PI, 0.0];
+
+        save_offsets(&path, offsets).unwrap();
+        assert_eq!(load_offsets(&path).unwrap(), Some(offsets));
+
+        // Keys find their module regardless of order
+        fs::write(
+            &path,
+            "# calibrated\nrear_right = 4\nfront_left = 1\n\nrear_left = 3\nfront_right = 2\n",
+        )
+        .unwrap();
+        assert_eq!(load_offsets(&path).unwrap(), Some([1.0, 2.0, 3.0, 4.0]));
+
+        fs::write(&path, "front_left = 1\nfront_right = 2\nrear_left = 3\n").unwrap();
+        assert!(load_offsets(&path).is_err());
+
+        fs::write(&path, "front_left = 1\nfront_left = 2\n").unwrap();
+        assert!(load_offsets(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(load_offsets(&path).unwrap(), None);
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::ensure;
+
+/// Position and velocity along a motion profile
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProfileState {
+    pub position: f32,
+    /// Units per second
+    pub velocity: f32,
+}
+
+/// A trapezoidal motion profile to a goal at rest. Like `SlewLimiter`, it is stepped with an
+/// explicit loop period so it agrees with the rest of the loop on dt.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrapezoidProfile {
+    /// Units per second
+    max_velocity: f32,
+    /// Units per second squared
+    max_accel: f32,
+}
+
+impl TrapezoidProfile {
+    pub fn new(max_velocity: f32, max_accel: f32) -> anyhow::Result<Self> {
+        ensure!(
+            max_velocity > 0.0 && max_velocity.is_finite(),
+            "Profile max velocity must be positive, got {max_velocity}"
+        );
+        ensure!(
+            max_accel > 0.0 && max_accel.is_finite(),
+            "Profile max acceleration must be positive, got {max_accel}"
+        );
+
+        Ok(Self {
+            max_velocity,
+            max_accel,
+        })
+    }
+
+    pub fn max_velocity(&self) -> f32 {
+        self.max_velocity
+    }
+
+    pub fn max_accel(&self) -> f32 {
+        self.max_accel
+    }
+
+    /// The state `dt` seconds after `state`, heading toward `goal`. Accelerates up to the max
+    /// velocity, then decelerates once the stopping distance reaches the remaining distance.
+    pub fn step(&self, state: ProfileState, goal: f32, dt: f32) -> ProfileState {
+        let error = goal - state.position;
+        let max_change = self.max_accel * dt;
+
+        let stopping_distance = state.velocity.powi(2) / (2.0 * self.max_accel);
+        let approaching = state.velocity.signum() == error.signum();
+
+        let velocity = if approaching && stopping_distance >= error.abs() {
+            state.velocity - state.velocity.signum() * max_change.min(state.velocity.abs())
+        } else {
+            (state.velocity + error.signum() * max_change)
+                .clamp(-self.max_velocity, self.max_velocity)
+        };
+
+        let step = velocity * dt;
+        if step.abs() >= error.abs() && step.signum() == error.signum() || error == 0.0 {
+            ProfileState {
+                position: goal,
+                velocity: 0.0,
+            }
+        } else {
+            ProfileState {
+                position: state.position + step,
+                velocity,
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt::Write;
+
+use anyhow::{anyhow, ensure, Context};
+use nalgebra::Vector2;
+
+/// One recorded driver input, in the units of `Drivetrain::set_input`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputSample {
+    /// Seconds since the start of the recording
+    pub time: f32,
+    pub drive: Vector2<f32>,
+    pub turn_rate: f32,
+}
+
+/// A recording of driver inputs, e.g. from `Drivetrain::stop_input_recording`, that can be
+/// replayed with `Drivetrain::replay_inputs`
+#[derive(Clone, Debug, Default)]
+pub struct InputTrace {
+    samples: Vec<InputSample>,
+}
+
+const CSV_HEADER: &str = "time,x,y,turn_rate";
+
+impl InputTrace {
+    pub fn new(samples: Vec<InputSample>) -> anyhow::Result<Self> {
+        ensure!(
+            samples.windows(2).all(|pair| pair[0].time <= pair[1].time),
+            "Input samples must be in time order"
+        );
+
+        Ok(Self { samples })
+    }
+
+    pub fn samples(&self) -> &[InputSample] {
+        &self.samples
+    }
+
+    /// Seconds
+    pub fn duration(&self) -> f32 {
+        self.samples.last().map_or(0.0, |sample| sample.time)
+    }
+
+    /// The most recent sample at `time` seconds into the recording
+    pub fn sample(&self, time: f32) -> Option<&InputSample> {
+        let next = self.samples.partition_point(|sample| sample.time <= time);
+
+        next.checked_sub(1).map(|index| &self.samples[index])
+    }
+
+    /// Parses CSV with a `time,x,y,turn_rate` header, as written by `to_csv`
+    pub fn from_csv(csv: &str) -> anyhow::Result<Self> {
+        let mut lines = csv.lines().map(str::trim).filter(|line| !line.is_empty());
+
+        ensure!(
+            lines.next() == Some(CSV_HEADER),
+            "Expected a `{CSV_HEADER}` header"
+        );
+
+        let samples = lines
+            .enumerate()
+            .map(|(row, line)| {
+                let values = line
+                    .split(',')
+                    .map(|value| value.trim().parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .with_context(|| format!("Invalid number in row {}", row + 1))?;
+
+                let [time, x, y, turn_rate] = values[..] else {
+                    return Err(anyhow!("Expected 4 columns in row {}", row + 1));
+                };
+
+                Ok(InputSample {
+                    time,
+                    drive: Vector2::new(x, y),
+                    turn_rate,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Self::new(samples)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{CSV_HEADER}\n");
+
+        for sample in &self.samples {
+            let _ = writeln!(
+                csv,
+                "{:.3},{:.4},{:.4},{:.4}",
+                sample.time, sample.drive.x, sample.drive.y, sample.turn_rate
+            );
+        }
+
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(time: f32, x: f32) -> InputSample {
+        InputSample {
+            time,
+            drive: Vector2::new(x, 0.0),
+            turn_rate: -x,
+        }
+    }
+
+    #[test]
+    fn csv_round_trips() {
+        let trace = InputTrace::new(vec![sample(0.0, 0.5), sample(0.02, 0.25)]).unwrap();
+        let csv = trace.to_csv();
+
+        assert!(csv.starts_with("time,x,y,turn_rate\n"));
+        assert_eq!(
+            InputTrace::from_csv(&csv).unwrap().samples(),
+            trace.samples()
+        );
+    }
+
+    #[test]
+    fn bad_csv_is_rejected() {
+        assert!(InputTrace::from_csv("time,x,y,heading\n0,0,0,0\n").is_err());
+        assert!(InputTrace::from_csv("0,0,0,0\n").is_err());
+        assert!(InputTrace::from_csv("time,x,y,turn_rate\n0,0,0\n").is_err());
+        assert!(InputTrace::from_csv("time,x,y,turn_rate\n0,0,zero,0\n").is_err());
+        assert!(InputTrace::from_csv("time,x,y,turn_rate\n1,0,0,0\n0,0,0,0\n").is_err());
+    }
+
+    #[test]
+    fn sample_holds_the_latest_input() {
+        let trace =
+            InputTrace::new(vec![sample(0.1, 1.0), sample(0.2, 2.0), sample(0.5, 3.0)]).unwrap();
+
+        assert_eq!(trace.duration(), 0.5);
+        assert_eq!(trace.sample(0.0), None);
+        assert_eq!(trace.sample(0.1), Some(&sample(0.1, 1.0)));
+        assert_eq!(trace.sample(0.3), Some(&sample(0.2, 2.0)));
+        assert_eq!(trace.sample(9.0), Some(&sample(0.5, 3.0)));
+    }
+}
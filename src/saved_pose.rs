@@ -0,0 +1,75 @@
+use std::{fs, io::ErrorKind, path::Path};
+
+use anyhow::{anyhow, ensure};
+use nalgebra::Vector3;
+
+/// Where the pose is persisted across code restarts on the roboRIO
+pub const POSE_PATH: &str = "/home/lvuser/swerve_pose.txt";
+
+/// Reads a pose stored as x and y in meters and heading in radians, one per line. Returns `None`
+/// if the file doesn't exist.
+pub fn load_pose(path: impl AsRef<Path>) -> anyhow::Result<Option<Vector3<f32>>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let values = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::parse::<f32>)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let [x, y, heading]: [f32; 3] = values.try_into().map_err(|values: Vec<f32>| {
+        anyhow!("Expected x, y and heading, found {} values", values.len())
+    })?;
+
+    ensure!(
+        [x, y, heading].iter().all(|value| value.is_finite()),
+        "Saved pose must be finite"
+    );
+
+    Ok(Some(Vector3::new(x, y, heading)))
+}
+
+/// Writes a pose in the format `load_pose` reads
+pub fn save_pose(path: impl AsRef<Path>, pose: Vector3<f32>) -> anyhow::Result<()> {
+    ensure!(
+        pose.iter().all(|value| value.is_finite()),
+        "Pose must be finite"
+    );
+
+    fs::write(path, format!("{}\n{}\n{}\n", pose.x, pose.y, pose.z))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn pose_round_trips_through_the_file() {
+        let path = env::temp_dir().join(format!("swerve_pose_{}.txt", std::process::id()));
+        let pose = Vector3::new(1.25, -3.5, 0.75);
+
+        save_pose(&path, pose).unwrap();
+        assert_eq!(load_pose(&path).unwrap(), Some(pose));
+
+        assert!(save_pose(&path, Vector3::new(f32::NAN, 0.0, 0.0)).is_err());
+        assert_eq!(load_pose(&path).unwrap(), Some(pose));
+
+        fs::write(&path, "1\n2\n").unwrap();
+        assert!(load_pose(&path).is_err());
+
+        fs::write(&path, "1\n2\ninf\n").unwrap();
+        assert!(load_pose(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(load_pose(&path).unwrap(), None);
+    }
+}
@@ -0,0 +1,41 @@
+use std::fmt;
+
+/// Outcome of the self test for a single module
+#[derive(Clone, Debug, PartialEq)]
+pub enum ModuleTestResult {
+    Passed,
+    Failed(String),
+    /// The wheel turned backward when driven forward, e.g. an inverted drive motor or encoder.
+    /// Holds the measured drive velocity in meters per second.
+    Inverted(f32),
+    /// Not run because an earlier module failed
+    Skipped,
+}
+
+/// Per module self test results, in module order
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfTestReport {
+    pub modules: [ModuleTestResult; 4],
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.modules
+            .iter()
+            .all(|result| *result == ModuleTestResult::Passed)
+    }
+}
+
+impl fmt::Display for ModuleTestResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleTestResult::Passed => write!(f, "passed"),
+            ModuleTestResult::Failed(reason) => write!(f, "failed: {reason}"),
+            ModuleTestResult::Inverted(velocity) => write!(
+                f,
+                "inverted: measured {velocity:.2} m/s while driving forward"
+            ),
+            ModuleTestResult::Skipped => write!(f, "skipped"),
+        }
+    }
+}
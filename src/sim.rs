@@ -0,0 +1,214 @@
+use std::{cell::RefCell, rc::Rc};
+
+use math::normalize_angle;
+use nalgebra::{Rotation2, Vector2};
+
+use crate::{
+    drivetrain::Drivetrain,
+    gyro::Gyro,
+    module_io::{ModuleIo, PositionReader},
+    swerve_module::{DriveControlMode, SwerveModule},
+};
+
+/// Everything a `SimModule` has been commanded and will report
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SimModuleState {
+    /// The last closed loop steering target, raw radians
+    pub turn_target: f32,
+    /// Raw absolute encoder angle in radians, within `[0, 2 * PI)`
+    pub turn_angle: f32,
+    /// Radians per second
+    pub turn_velocity: f32,
+    /// The last open loop turn voltage, cleared by closed loop steering or a stop
+    pub turn_voltage: Option<f32>,
+    /// The last drive target, meters per second or amps depending on `drive_mode`
+    pub drive_target: f32,
+    pub drive_mode: DriveControlMode,
+    /// Meters
+    pub drive_position: f32,
+    /// Meters per second
+    pub drive_velocity: f32,
+    /// Amps
+    pub drive_current: f32,
+    pub drive_coast: bool,
+    /// Simulates a drive motor or encoder wired backward, so the wheel measures the opposite of
+    /// its velocity command
+    pub drive_inverted: bool,
+    pub applied_outputs: (f32, f32),
+    pub drive_current_limit: Option<u8>,
+    pub turn_current_limit: Option<u8>,
+    /// While set, commands are recorded but the measured angle and speed stay wherever they were
+    /// put, e.g. to simulate a module that is stuck or slow to respond
+    pub frozen: bool,
+}
+
+/// Module hardware that follows every command perfectly: the wheel is always at the commanded
+/// angle and speed. Clones share the same state, so a test can keep one to read back commands
+/// and change the measurements the drivetrain sees.
+#[derive(Clone, Default)]
+pub struct SimModule {
+    state: Rc<RefCell<SimModuleState>>,
+}
+
+impl SimModule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> SimModuleState {
+        *self.state.borrow()
+    }
+
+    /// Changes the simulated state, e.g. to move the wheel by hand
+    pub fn update(&self, f: impl FnOnce(&mut SimModuleState)) {
+        f(&mut self.state.borrow_mut());
+    }
+
+    /// Advances time by `dt` seconds, driving the wheel at its measured velocity
+    pub fn step(&self, dt: f32) {
+        let mut state = self.state.borrow_mut();
+        state.drive_position += state.drive_velocity * dt;
+    }
+}
+
+impl ModuleIo for SimModule {
+    fn set_turn_position(&mut self, radians: f32) -> anyhow::Result<()> {
+        self.update(|state| {
+            state.turn_target = radians;
+            state.turn_voltage = None;
+
+            if !state.frozen {
+                state.turn_angle = normalize_angle(radians);
+            }
+        });
+
+        Ok(())
+    }
+
+    fn set_turn_voltage(&mut self, volts: f32) -> anyhow::Result<()> {
+        self.update(|state| state.turn_voltage = Some(volts));
+
+        Ok(())
+    }
+
+    fn stop_turn(&mut self) {
+        self.update(|state| {
+            state.turn_voltage = None;
+
+            if !state.frozen {
+                state.turn_velocity = 0.0;
+            }
+        });
+    }
+
+    fn set_drive(&mut self, value: f32, mode: DriveControlMode) -> anyhow::Result<()> {
+        self.update(|state| {
+            state.drive_target = value;
+            state.drive_mode = mode;
+
+            if !state.frozen && mode == DriveControlMode::Velocity {
+                state.drive_velocity = if state.drive_inverted { -value } else { value };
+            }
+        });
+
+        Ok(())
+    }
+
+    fn stop_drive(&mut self) {
+        self.update(|state| {
+            state.drive_target = 0.0;
+
+            if !state.frozen {
+                state.drive_velocity = 0.0;
+            }
+        });
+    }
+
+    fn set_drive_coast(&mut self, coast: bool) -> anyhow::Result<()> {
+        self.update(|state| state.drive_coast = coast);
+
+        Ok(())
+    }
+
+    fn set_drive_current_limit(&mut self, amps: u8) -> anyhow::Result<()> {
+        self.update(|state| state.drive_current_limit = Some(amps));
+
+        Ok(())
+    }
+
+    fn set_turn_current_limit(&mut self, amps: u8) -> anyhow::Result<()> {
+        self.update(|state| state.turn_current_limit = Some(amps));
+
+        Ok(())
+    }
+
+    fn turn_angle(&mut self) -> anyhow::Result<f32> {
+        Ok(self.state().turn_angle)
+    }
+
+    fn turn_velocity(&mut self) -> anyhow::Result<f32> {
+        Ok(self.state().turn_velocity)
+    }
+
+    fn drive_position(&mut self) -> anyhow::Result<f32> {
+        Ok(self.state().drive_position)
+    }
+
+    fn set_drive_position(&mut self, meters: f32) -> anyhow::Result<()> {
+        self.update(|state| state.drive_position = meters);
+
+        Ok(())
+    }
+
+    fn drive_velocity(&mut self) -> anyhow::Result<f32> {
+        Ok(self.state().drive_velocity)
+    }
+
+    fn drive_current(&mut self) -> anyhow::Result<f32> {
+        Ok(self.state().drive_current)
+    }
+
+    fn applied_outputs(&mut self) -> anyhow::Result<(f32, f32)> {
+        Ok(self.state().applied_outputs)
+    }
+
+    fn position_reader(&mut self) -> anyhow::Result<PositionReader> {
+        let module = self.clone();
+
+        Ok(Box::new(move || {
+            let state = module.state();
+
+            Ok((state.turn_angle, state.drive_position))
+        }))
+    }
+}
+
+/// A drivetrain on `SimModule`s with zero offsets, returned along with handles to the modules in
+/// module order. Nothing updates odometry in the background; feed it with
+/// `Drivetrain::update_odometry`.
+pub fn sim_drivetrain(
+    gyro: impl Gyro + 'static,
+    module_positions: [Vector2<f32>; 4],
+) -> anyhow::Result<(Drivetrain, [SimModule; 4])> {
+    let sims = [
+        SimModule::new(),
+        SimModule::new(),
+        SimModule::new(),
+        SimModule::new(),
+    ];
+
+    let module = |sim: &SimModule| {
+        SwerveModule::new(sim.clone(), Rotation2::identity(), 0.0).map(|(module, _)| module)
+    };
+    let modules = [
+        module(&sims[0])?,
+        module(&sims[1])?,
+        module(&sims[2])?,
+        module(&sims[3])?,
+    ];
+
+    Ok((
+        Drivetrain::from_modules(gyro, modules, module_positions)?,
+        sims,
+    ))
+}
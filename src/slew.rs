@@ -0,0 +1,113 @@
+/// A slew rate limiter that is stepped with an explicit loop period instead of measuring time
+/// itself, so every consumer in a loop agrees on the same dt. It can optionally limit jerk too,
+/// which turns a step input into an S-shaped ramp instead of a linear one.
+#[derive(Clone, Debug)]
+pub struct SlewLimiter {
+    /// Units per second
+    rate: f32,
+    /// Units per second squared
+    jerk: Option<f32>,
+    value: f32,
+    /// Units per second, only tracked when jerk limited
+    velocity: f32,
+}
+
+impl SlewLimiter {
+    pub fn new(rate: f32) -> Self {
+        Self {
+            rate,
+            jerk: None,
+            value: 0.0,
+            velocity: 0.0,
+        }
+    }
+
+    pub fn set_jerk_limit(&mut self, jerk: Option<f32>) {
+        self.jerk = jerk;
+        self.velocity = 0.0;
+    }
+
+    pub fn apply(&mut self, input: f32, dt: f32) -> f32 {
+        let error = input - self.value;
+
+        match self.jerk {
+            None => {
+                let max_change = self.rate * dt;
+                self.value += error.clamp(-max_change, max_change);
+            }
+            Some(jerk) => {
+                // Fastest rate that can still be brought to zero by the time the input is reached
+                let desired = error.signum() * self.rate.min((2.0 * jerk * error.abs()).sqrt());
+                let max_change = jerk * dt;
+                self.velocity += (desired - self.velocity).clamp(-max_change, max_change);
+
+                let step = self.velocity * dt;
+                if step.abs() >= error.abs() && step.signum() == error.signum() {
+                    self.value = input;
+                    self.velocity = 0.0;
+                } else {
+                    self.value += step;
+                }
+            }
+        }
+
+        self.value
+    }
+
+    /// Jumps straight to `value` with no rate of change, so the next step starts from rest there
+    pub fn reset(&mut self, value: f32) {
+        self.value = value;
+        self.velocity = 0.0;
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steps from rest toward 1 until it gets there, returning every value on the way
+    fn ramp(limiter: &mut SlewLimiter, dt: f32) -> Vec<f32> {
+        let mut values = Vec::new();
+
+        while limiter.value() < 1.0 {
+            values.push(limiter.apply(1.0, dt));
+            assert!(values.len() < 10_000, "the ramp never reached the input");
+        }
+
+        values
+    }
+
+    #[test]
+    fn rate_limit_ramps_linearly() {
+        let values = ramp(&mut SlewLimiter::new(2.0), 0.125);
+
+        assert_eq!(values, [0.25, 0.5, 0.75, 1.0]);
+    }
+
+    #[test]
+    fn jerk_limit_ramps_in_an_s_curve() {
+        let mut limiter = SlewLimiter::new(2.0);
+        limiter.set_jerk_limit(Some(10.0));
+
+        let values = ramp(&mut limiter, 0.01);
+        let steps = values
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect::<Vec<_>>();
+
+        // Never moves backward or past the input
+        assert!(steps.iter().all(|step| *step >= 0.0));
+        assert!(values.iter().all(|value| *value <= 1.0));
+        assert_eq!(*values.last().unwrap(), 1.0);
+
+        // Speeds up gently from rest, and slows down again before arriving
+        let fastest = steps.iter().copied().fold(0.0, f32::max);
+        assert!(steps[0] < fastest / 2.0);
+        assert!(steps[steps.len() - 2] < fastest / 2.0);
+        assert!(fastest <= 2.0 * 0.01 + 1e-6);
+    }
+}
@@ -1,146 +1,552 @@
 use nalgebra::Rotation2;
-use revlib::{encoder::Encoder, SparkMax};
-use robotrs::{
-    control::ControlSafe,
-    motor::{IdleMode, SetIdleMode},
-};
+use robotrs::control::ControlSafe;
 
+use anyhow::ensure;
 use math::{kinematics::SwerveState, normalize_angle};
-use std::f32::consts::PI;
-
-const WHEEL_DIAMETER: f32 = 3.0; // inches
+use std::{
+    cell::Cell,
+    f32::consts::{FRAC_PI_2, PI},
+    ops::RangeInclusive,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
-const TURN_POSITION_CONVERSION_FACTOR: f32 = 2.0 * PI;
-// rotations to radians
-const TURN_VELOCITY_CONVERSION_FACTOR: f32 = TURN_POSITION_CONVERSION_FACTOR / 60.0;
-// radians per minute to radians per second
-const DRIVE_POSITION_CONVERSION_FACTOR: f32 = (WHEEL_DIAMETER * PI) / (4.71 * 39.3701);
-// ^ 4.71 is gear reduction, 39.3701 is inches per meter
-const DRIVE_VELOCITY_CONVERSION_FACTOR: f32 = DRIVE_POSITION_CONVERSION_FACTOR / 60.0;
-// meter per minute to meter per second
+use crate::{module_io::ModuleIo, util::wrap_angle};
 
 const TURN_P: f32 = 1.0;
 const TURN_I: f32 = 0.0;
 const TURN_D: f32 = 0.0;
 const TURN_F: f32 = 0.0;
 
+/// Meters per second
+pub(crate) const DRIVE_FREE_SPEED: f32 = 1.530471338;
+
 const DRIVE_P: f32 = 0.04;
 const DRIVE_I: f32 = 0.0;
 const DRIVE_D: f32 = 0.0;
-const DRIVE_F: f32 = 1.0 / 1.530471338; // based on free speed
+const DRIVE_F: f32 = 1.0 / DRIVE_FREE_SPEED; // based on free speed
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PidGains {
+    pub p: f32,
+    pub i: f32,
+    pub d: f32,
+    pub f: f32,
+}
 
-const TURN_MAX_OUTPUT: f32 = 1.0;
-const TURN_MIN_OUTPUT: f32 = -1.0;
-const DRIVE_MAX_OUTPUT: f32 = 1.0;
-const DRIVE_MIN_OUTPUT: f32 = -1.0;
+/// Closed loop gains for one module, so a corner that behaves differently can be tuned alone
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModuleGains {
+    pub turn: PidGains,
+    pub drive: PidGains,
+}
+
+impl ModuleGains {
+    pub const DEFAULT: ModuleGains = ModuleGains {
+        turn: PidGains {
+            p: TURN_P,
+            i: TURN_I,
+            d: TURN_D,
+            f: TURN_F,
+        },
+        drive: PidGains {
+            p: DRIVE_P,
+            i: DRIVE_I,
+            d: DRIVE_D,
+            f: DRIVE_F,
+        },
+    };
+}
 
-const TURN_MAX_CURRENT: u8 = 20; // amps
-const DRIVE_MAX_CURRENT: u8 = 50;
+impl Default for ModuleGains {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
 
-const TURN_IDLE_MODE: IdleMode = IdleMode::Brake;
-const DRIVE_IDLE_MODE: IdleMode = IdleMode::Brake;
+/// Current limits accepted at runtime, in amps
+const CURRENT_LIMIT_RANGE: RangeInclusive<u8> = 5..=80;
+
+/// Meters per second. Targets slower than this keep the previous angle instead of steering.
+const STEER_DEADBAND: f32 = 0.001;
+
+/// Radians of steering error beyond which optimize flips the wheel and reverses the drive instead
+const OPTIMIZE_THRESHOLD: f32 = FRAC_PI_2;
+
+/// Absolute encoder reads averaged for the starting turn angle, at least 1
+const STARTING_TURN_SAMPLES: usize = 5;
+
+/// What the drive motor's closed loop controls
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DriveControlMode {
+    /// Target drive values are meters per second
+    #[default]
+    Velocity,
+    /// Target drive values are amps, which is proportional to wheel torque. Nothing limits the
+    /// resulting speed: a constant current keeps accelerating until the robot hits something or
+    /// the current limit is reached, and a zero target coasts rather than stopping the wheel.
+    /// Keep commands small and watch the robot when experimenting with this.
+    Current,
+}
 
 pub struct SwerveModule {
-    turn: SparkMax,
-    drive: SparkMax,
+    io: Box<dyn ModuleIo>,
     current_state: SwerveState,
-    offset: f32,
+    /// Whether optimize flipped the last target, so the wheel drives opposite to the request
+    reversed: bool,
+    steer_deadband: f32,
+    optimize_threshold: f32,
+    drive_mode: DriveControlMode,
+    /// How long the module holds still before the turn motor is relaxed, `None` to always hold
+    turn_idle_relax: Option<Duration>,
+    idle_since: Option<Instant>,
+    turn_relaxed: bool,
+    /// Radians, shared with the odometry closure so recalibrating takes effect there too
+    offset: Rc<Cell<f32>>,
+    /// Meters, the drive position the odometry closure last read, so moving the baseline doesn't
+    /// show up as distance travelled
+    last_drive_position: Rc<Cell<f32>>,
 }
 
 impl SwerveModule {
+    /// Wraps the module hardware, e.g. a `SparkMaxModule`, returning the module along with a
+    /// reader of the distance driven since its last call and the measured angle, for odometry
     pub fn new(
-        drive_id: i32,
-        turn_id: i32,
+        mut io: impl ModuleIo + 'static,
         angle_offset: Rotation2<f32>,
+        initial_drive_position: f32,
     ) -> anyhow::Result<(Self, impl FnMut() -> anyhow::Result<SwerveState> + 'static)> {
-        let mut turn = SparkMax::new(turn_id, revlib::MotorType::Brushless)?;
-        let mut drive = SparkMax::new(drive_id, revlib::MotorType::Brushless)?;
-
-        turn.reset_settings()?;
-        drive.reset_settings()?;
-
-        turn.set_pid_range(TURN_MIN_OUTPUT..=TURN_MAX_OUTPUT)?;
-        drive.set_pid_range(DRIVE_MIN_OUTPUT..=DRIVE_MAX_OUTPUT)?;
-
-        let mut turn_encoder = turn.get_absolute_encoder()?;
-        let mut drive_encoder = drive.get_relative_encoder()?;
-
-        turn_encoder.set_inverted(true)?;
-
-        turn_encoder.set_position_conversion_factor(TURN_POSITION_CONVERSION_FACTOR)?;
-        turn_encoder.set_velocity_conversion_factor(TURN_VELOCITY_CONVERSION_FACTOR)?;
-        drive_encoder.set_position_conversion_factor(DRIVE_POSITION_CONVERSION_FACTOR)?;
-        drive_encoder.set_velocity_conversion_factor(DRIVE_VELOCITY_CONVERSION_FACTOR)?;
-
-        turn.set_pid(TURN_P, TURN_D, TURN_I, TURN_F)?;
-        drive.set_pid(DRIVE_P, DRIVE_D, DRIVE_I, DRIVE_F)?;
-
-        turn.set_pid_range(-1.0..=1.0)?;
-        drive.set_pid_range(-1.0..=1.0)?;
+        ensure!(
+            initial_drive_position.is_finite(),
+            "Drive position must be finite"
+        );
 
-        turn.set_wrapping(true, 0.0, 2.0 * PI)?;
+        let starting_turn = average_angle(STARTING_TURN_SAMPLES, || io.turn_angle())?;
 
-        let starting_turn = turn_encoder.get_position()?;
+        io.set_drive_position(initial_drive_position)?;
+        let mut read_position = io.position_reader()?;
 
-        turn.set_pid_input(&turn_encoder)?;
-        drive.set_pid_input(&drive_encoder)?;
+        let offset = Rc::new(Cell::new(normalize_angle(angle_offset.angle())));
+        let offset2 = offset.clone();
 
-        turn.set_smart_current_limit(TURN_MAX_CURRENT)?;
-        drive.set_smart_current_limit(DRIVE_MAX_CURRENT)?;
+        // Targets are relative to the offset, like every angle outside the hardware
+        let starting_state = SwerveState::new(normalize_angle(starting_turn - offset.get()), 0.0);
 
-        turn.set_idle_mode(TURN_IDLE_MODE)?;
-        drive.set_idle_mode(DRIVE_IDLE_MODE)?;
-
-        turn.write_settings()?;
-        drive.write_settings()?;
-
-        drive_encoder.set_position(0.0)?;
-
-        let offset = normalize_angle(angle_offset.angle());
-
-        let mut last_position = 0.0;
+        let last_drive_position = Rc::new(Cell::new(initial_drive_position));
+        let last_drive_position2 = last_drive_position.clone();
 
         Ok((
             Self {
-                turn,
-                drive,
-                current_state: SwerveState::new(starting_turn, 0.0),
+                io: Box::new(io),
+                current_state: starting_state,
+                reversed: false,
+                steer_deadband: STEER_DEADBAND,
+                optimize_threshold: OPTIMIZE_THRESHOLD,
+                drive_mode: DriveControlMode::default(),
+                turn_idle_relax: None,
+                idle_since: None,
+                turn_relaxed: false,
                 offset,
+                last_drive_position,
             },
             move || {
-                let new_position = drive_encoder.get_position()?;
+                let (angle, new_position) = read_position()?;
 
                 let res = Ok(SwerveState {
-                    drive: new_position - last_position,
-                    angle: turn_encoder.get_position()? - offset,
+                    drive: new_position - last_drive_position2.get(),
+                    angle: angle - offset2.get(),
                 });
 
-                last_position = new_position;
+                last_drive_position2.set(new_position);
 
                 res
             },
         ))
     }
 
-    pub fn set_target(&mut self, state: SwerveState) -> anyhow::Result<()> {
+    /// Below this drive speed (m/s) a target keeps the previous angle, since the angle of a
+    /// near zero vector is noise
+    pub fn set_steer_deadband(&mut self, deadband: f32) {
+        self.steer_deadband = deadband;
+    }
+
+    /// Steering error in radians beyond which the wheel is flipped and the drive reversed. Above
+    /// PI / 2 the drive reverses less often at the cost of more steering, below it the opposite.
+    pub fn set_optimize_threshold(&mut self, threshold: f32) -> anyhow::Result<()> {
+        ensure!(
+            threshold > 0.0 && threshold < PI,
+            "Optimize threshold must be between 0 and PI, got {threshold}"
+        );
+
+        self.optimize_threshold = threshold;
+
+        Ok(())
+    }
+
+    /// Commands a target, returning the state actually sent after the steer deadband and
+    /// optimize
+    pub fn set_target(&mut self, state: SwerveState) -> anyhow::Result<SwerveState> {
+        self.wake()?;
+
+        let state = if state.drive.abs() < self.steer_deadband {
+            SwerveState::new(self.current_state.get_angle(), state.drive)
+        } else {
+            state
+        };
+
+        self.set_target_steered(state)
+    }
+
+    /// Like `set_target`, but steers to the requested angle even inside the steer deadband, for
+    /// targets where the angle matters at zero speed like the X-stance
+    pub fn set_target_steered(&mut self, state: SwerveState) -> anyhow::Result<SwerveState> {
         // dbg!(state);
-        let state = state.optimize(self.current_state);
+        self.wake()?;
+        let optimized = optimize(state, self.current_state, self.optimize_threshold);
+
+        self.set_target_raw(optimized)?;
+        self.reversed = wrap_angle(optimized.angle - state.angle).abs() > PI / 2.0;
+
+        Ok(optimized)
+    }
+
+    /// Commands exactly the given angle and drive speed, skipping `optimize`. The offset is still
+    /// applied.
+    pub fn set_target_raw(&mut self, state: SwerveState) -> anyhow::Result<()> {
+        self.wake()?;
+        self.command(state)
+    }
+
+    fn command(&mut self, state: SwerveState) -> anyhow::Result<()> {
         self.current_state = state;
+        self.reversed = false;
+
+        self.io
+            .set_turn_position(state.get_angle() + self.offset.get())?;
+        self.io.set_drive(state.get_drive(), self.drive_mode)?;
+
+        Ok(())
+    }
+
+    /// Switches what target drive values mean. The drive motor is commanded to zero in the new
+    /// mode so the setpoint from the old mode doesn't stay active.
+    pub fn set_drive_control_mode(&mut self, mode: DriveControlMode) -> anyhow::Result<()> {
+        if mode == self.drive_mode {
+            return Ok(());
+        }
+
+        self.drive_mode = mode;
+        self.current_state = SwerveState::new(self.current_state.get_angle(), 0.0);
+        self.io.set_drive(0.0, mode)?;
+
+        Ok(())
+    }
+
+    pub fn drive_control_mode(&self) -> DriveControlMode {
+        self.drive_mode
+    }
+
+    /// Commands the drive motor to `amps` at the current steering angle, switching to current
+    /// control if needed. See `DriveControlMode::Current` before using this.
+    pub fn set_drive_current_command(&mut self, amps: f32) -> anyhow::Result<()> {
+        self.set_drive_control_mode(DriveControlMode::Current)?;
+        self.set_target_raw(SwerveState::new(self.current_state.get_angle(), amps))
+    }
+
+    /// Lets the drive motor coast when not driven, or restores the configured idle mode
+    pub fn set_drive_coast(&mut self, coast: bool) -> anyhow::Result<()> {
+        self.io.set_drive_coast(coast)
+    }
+
+    /// Replaces the drive motor's smart current limit and writes it to the controller
+    pub fn set_drive_current_limit(&mut self, amps: u8) -> anyhow::Result<()> {
+        check_current_limit(amps)?;
+        self.io.set_drive_current_limit(amps)
+    }
+
+    /// Replaces the turn motor's smart current limit and writes it to the controller
+    pub fn set_turn_current_limit(&mut self, amps: u8) -> anyhow::Result<()> {
+        check_current_limit(amps)?;
+        self.io.set_turn_current_limit(amps)
+    }
+
+    /// The last commanded state, after optimization
+    pub fn target_state(&self) -> SwerveState {
+        self.current_state
+    }
+
+    /// Whether the measured angle is within `tolerance` radians of the last commanded angle
+    pub fn at_angle_target(&mut self, tolerance: f32) -> anyhow::Result<bool> {
+        Ok(wrap_angle(self.get_angle()? - self.current_state.get_angle()).abs() <= tolerance)
+    }
+
+    /// Stops driving while holding the current steering angle. With idle relax enabled, the turn
+    /// motor is released once the module has been held for the relax delay.
+    pub fn hold(&mut self) -> anyhow::Result<()> {
+        if let Some(delay) = self.turn_idle_relax {
+            if self.turn_relaxed {
+                return Ok(());
+            }
+
+            if self.idle_since.get_or_insert_with(Instant::now).elapsed() >= delay {
+                self.io.stop_turn();
+                self.turn_relaxed = true;
+
+                return Ok(());
+            }
+        }
+
+        self.command(SwerveState::new(self.current_state.get_angle(), 0.0))
+    }
+
+    /// When enabled, a module held still for `delay` stops driving its turn motor instead of
+    /// holding the angle against the PID, saving current and motor whine at standstill. The next
+    /// command re-engages it.
+    pub fn set_turn_idle_relax(&mut self, enabled: bool, delay: Duration) {
+        self.turn_idle_relax = enabled.then_some(delay);
+        self.idle_since = None;
+    }
+
+    /// Ends any idle period before a new command. If the turn motor was relaxed the wheel may have
+    /// been pushed since, so the measured angle replaces the old setpoint to optimize against.
+    fn wake(&mut self) -> anyhow::Result<()> {
+        self.idle_since = None;
+
+        if self.turn_relaxed {
+            self.current_state = SwerveState::new(self.get_angle()?, 0.0);
+            self.turn_relaxed = false;
+        }
 
-        self.turn.set_reference(
-            state.get_angle() + self.offset,
-            revlib::ControlType::Position,
-        )?;
-        self.drive
-            .set_reference(state.get_drive(), revlib::ControlType::Velocity)?;
+        Ok(())
+    }
+
+    /// Measured module angle in radians, with the offset removed
+    pub fn get_angle(&mut self) -> anyhow::Result<f32> {
+        Ok(self.get_raw_angle()? - self.offset.get())
+    }
+
+    /// Absolute encoder angle in radians, without the offset
+    pub(crate) fn get_raw_angle(&mut self) -> anyhow::Result<f32> {
+        self.io.turn_angle()
+    }
+
+    /// The active angle offset in radians, normalized
+    pub fn get_offset(&self) -> f32 {
+        self.offset.get()
+    }
+
+    /// Replaces the offset (radians, normalized here) and re-sends the current target so the
+    /// wheel steers against the new zero immediately
+    pub fn set_offset(&mut self, offset: f32) -> anyhow::Result<()> {
+        self.offset.set(normalize_angle(offset));
+
+        let reversed = self.reversed;
+        self.set_target_raw(self.current_state)?;
+        self.reversed = reversed;
+
+        Ok(())
+    }
+
+    /// Measured angle and drive velocity in meters per second
+    pub fn get_state(&mut self) -> anyhow::Result<SwerveState> {
+        Ok(SwerveState::new(
+            self.get_angle()?,
+            self.io.drive_velocity()?,
+        ))
+    }
+
+    /// Measured angle and drive distance in meters
+    pub fn get_position(&mut self) -> anyhow::Result<SwerveState> {
+        Ok(SwerveState::new(
+            self.get_angle()?,
+            self.io.drive_position()?,
+        ))
+    }
+
+    /// Drives the turn motor open loop at `volts` with the drive motor stopped, for
+    /// characterization. The next target returns it to closed loop.
+    pub fn set_turn_voltage(&mut self, volts: f32) -> anyhow::Result<()> {
+        self.io.stop_drive();
+        self.io.set_turn_voltage(volts)
+    }
+
+    /// Radians per second
+    pub fn get_turn_velocity(&mut self) -> anyhow::Result<f32> {
+        self.io.turn_velocity()
+    }
+
+    /// Moves the drive distance reported by `get_position` to `meters` without affecting
+    /// odometry
+    pub fn set_drive_position(&mut self, meters: f32) -> anyhow::Result<()> {
+        ensure!(meters.is_finite(), "Drive position must be finite");
+
+        self.io.set_drive_position(meters)?;
+        self.last_drive_position.set(meters);
 
         Ok(())
     }
+
+    /// Amps
+    pub fn get_drive_current(&mut self) -> anyhow::Result<f32> {
+        self.io.drive_current()
+    }
+
+    /// Applied output of the drive and turn motors as a fraction of bus voltage, -1 to 1. Near 1
+    /// with the wheel not moving means stalled, near 1 while short of the setpoint means
+    /// saturated, and near 0 with a nonzero setpoint means nothing is being commanded.
+    pub fn applied_outputs(&mut self) -> anyhow::Result<(f32, f32)> {
+        self.io.applied_outputs()
+    }
+
+    /// Meters per second, signed relative to the last requested (pre-optimize) direction
+    pub fn get_drive_velocity(&mut self) -> anyhow::Result<f32> {
+        let velocity = self.io.drive_velocity()?;
+
+        Ok(if self.reversed { -velocity } else { velocity })
+    }
+}
+
+/// Flips `state` to the opposite angle with the drive reversed when it is more than `threshold`
+/// radians of steering away from `current`
+fn optimize(state: SwerveState, current: SwerveState, threshold: f32) -> SwerveState {
+    if wrap_angle(state.angle - current.angle).abs() > threshold {
+        SwerveState::new(normalize_angle(state.angle + PI), -state.drive)
+    } else {
+        state
+    }
+}
+
+/// Circular mean of `samples` back to back angle reads, so one noisy read can't seed a bad state
+/// and reads either side of the wrap don't average to the opposite direction. Nothing waits
+/// between reads, since this runs in the constructor and must not block the scheduler.
+fn average_angle(
+    samples: usize,
+    mut read: impl FnMut() -> anyhow::Result<f32>,
+) -> anyhow::Result<f32> {
+    ensure!(samples >= 1, "At least one angle sample is needed");
+
+    let (mut sin, mut cos) = (0.0, 0.0);
+
+    for _ in 0..samples {
+        let angle = read()?;
+        sin += angle.sin();
+        cos += angle.cos();
+    }
+
+    Ok(normalize_angle(sin.atan2(cos)))
+}
+
+fn check_current_limit(amps: u8) -> anyhow::Result<()> {
+    ensure!(
+        CURRENT_LIMIT_RANGE.contains(&amps),
+        "Current limit of {amps}A is outside {CURRENT_LIMIT_RANGE:?}"
+    );
+
+    Ok(())
 }
 
 impl ControlSafe for SwerveModule {
     fn stop(&mut self) {
-        self.turn.stop();
-        self.drive.stop();
+        self.io.stop_turn();
+        self.io.stop_drive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimModule;
+
+    fn sim_module() -> (SwerveModule, SimModule) {
+        let sim = SimModule::new();
+        let (module, _) = SwerveModule::new(sim.clone(), Rotation2::identity(), 0.0).unwrap();
+
+        (module, sim)
+    }
+
+    #[test]
+    fn starting_state_is_relative_to_the_offset() {
+        let sim = SimModule::new();
+        sim.update(|state| state.turn_angle = FRAC_PI_2 + 0.3);
+
+        let (mut module, _) =
+            SwerveModule::new(sim.clone(), Rotation2::new(FRAC_PI_2), 0.0).unwrap();
+        assert!((module.target_state().angle - 0.3).abs() < 1e-5);
+
+        // Holding before any target keeps the wheel where it is
+        module.hold().unwrap();
+        assert!((sim.state().turn_target - (FRAC_PI_2 + 0.3)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn set_target_flips_past_a_quarter_turn() {
+        let (mut module, sim) = sim_module();
+
+        // Close enough to steer to directly
+        let sent = module.set_target(SwerveState::new(0.25 * PI, 1.0)).unwrap();
+        assert_eq!((sent.angle, sent.drive), (0.25 * PI, 1.0));
+        assert_eq!(sim.state().drive_target, 1.0);
+        assert_eq!(module.get_drive_velocity().unwrap(), 1.0);
+
+        // Most of a half turn away, so the wheel turns the short way and drives backwards
+        let sent = module.set_target(SwerveState::new(1.1 * PI, 1.0)).unwrap();
+        assert!((sent.angle - 0.1 * PI).abs() < 1e-5);
+        assert_eq!(sent.drive, -1.0);
+
+        let state = sim.state();
+        assert!((state.turn_target - 0.1 * PI).abs() < 1e-5);
+        assert_eq!(state.drive_target, -1.0);
+
+        // Measured speed is reported in the requested direction
+        assert_eq!(module.get_drive_velocity().unwrap(), 1.0);
+    }
+
+    #[test]
+    fn optimize_threshold_moves_the_flip_point() {
+        let (mut module, sim) = sim_module();
+
+        assert!(module.set_optimize_threshold(0.0).is_err());
+        assert!(module.set_optimize_threshold(PI).is_err());
+
+        // Past a quarter turn, but inside the wider threshold
+        module.set_optimize_threshold(0.9 * PI).unwrap();
+        let sent = module.set_target(SwerveState::new(0.8 * PI, 1.0)).unwrap();
+        assert!((sent.angle - 0.8 * PI).abs() < 1e-5);
+        assert_eq!(sim.state().drive_target, 1.0);
+
+        // A narrow threshold flips targets a quarter turn away
+        module.set_optimize_threshold(0.25 * PI).unwrap();
+        let sent = module.set_target(SwerveState::new(0.3 * PI, 1.0)).unwrap();
+        assert!((sent.angle - 1.3 * PI).abs() < 1e-5);
+        assert_eq!(sim.state().drive_target, -1.0);
+    }
+
+    #[test]
+    fn drive_baseline_is_reported_but_not_driven() {
+        let sim = SimModule::new();
+        let (mut module, mut read_odometry) =
+            SwerveModule::new(sim.clone(), Rotation2::identity(), 1.5).unwrap();
+
+        assert_eq!(module.get_position().unwrap().drive, 1.5);
+        assert_eq!(read_odometry().unwrap().drive, 0.0);
+
+        module.set_drive_position(4.0).unwrap();
+        assert_eq!(module.get_position().unwrap().drive, 4.0);
+        assert_eq!(read_odometry().unwrap().drive, 0.0);
+
+        sim.update(|state| state.drive_position += 0.25);
+        assert_eq!(read_odometry().unwrap().drive, 0.25);
+
+        assert!(module.set_drive_position(f32::NAN).is_err());
+        assert!(SwerveModule::new(SimModule::new(), Rotation2::identity(), f32::INFINITY).is_err());
+    }
+
+    #[test]
+    fn average_angle_takes_the_circular_mean() {
+        let mut reads = [0.1, 2.0 * PI - 0.1, 0.3].into_iter();
+        let average = average_angle(3, || Ok(reads.next().unwrap())).unwrap();
+        assert!((average - 0.1).abs() < 1e-3, "{average}");
+
+        assert!(average_angle(0, || Ok(0.0)).is_err());
     }
 }
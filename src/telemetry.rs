@@ -0,0 +1,29 @@
+use math::kinematics::SwerveState;
+use nalgebra::{Vector2, Vector3};
+
+/// A point in time view of the drivetrain for logging and dashboards. Sensor reads that failed
+/// are `None` rather than failing the whole snapshot. Modules are in the order front left, front
+/// right, rear left, rear right.
+#[derive(Clone, Debug)]
+pub struct DrivetrainSnapshot {
+    /// Field relative x, y (meters) and heading (radians)
+    pub pose: Vector3<f32>,
+    /// Radians, counterclockwise positive
+    pub heading: f32,
+    /// Field relative meters per second, from forward kinematics
+    pub velocity: Option<Vector2<f32>>,
+    /// Measured angle and drive velocity (m/s)
+    pub module_states: [Option<SwerveState>; 4],
+    /// Measured angle and drive distance (m)
+    pub module_positions: [Option<SwerveState>; 4],
+    /// Drive motor output current in amps
+    pub currents: [Option<f32>; 4],
+    /// Commanded minus measured drive velocity (m/s)
+    pub drive_velocity_errors: [Option<f32>; 4],
+    /// Drive and turn applied output as a fraction of bus voltage, -1 to 1
+    pub applied_outputs: [Option<(f32, f32)>; 4],
+    /// Radians per second, counterclockwise positive
+    pub gyro_rate: f32,
+    /// Hz the odometry task is updating at
+    pub odometry_rate: f32,
+}
@@ -0,0 +1,385 @@
+use anyhow::ensure;
+use math::kinematics::{Kinematics, SwerveKinematics};
+use nalgebra::{Rotation2, Vector3};
+
+use crate::util::wrap_angle;
+
+/// How the heading target moves between two samples
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeadingInterpolation {
+    /// Turn the short way round, ignoring whole turns between the two headings
+    #[default]
+    ShortestPath,
+    /// Interpolate the stored headings literally, so the winding is explicit: going from 0 to
+    /// 2PI spins a full turn counterclockwise, and from 0 to -PI/2 turns clockwise
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrajectorySample {
+    /// Seconds since the start of the trajectory
+    pub time: f32,
+    /// Field relative x, y (meters) and heading (radians)
+    pub pose: Vector3<f32>,
+    /// Field relative vx, vy (meters per second) and omega (radians per second)
+    pub velocity: Vector3<f32>,
+    /// Heading interpolation for the segment from this sample to the next
+    pub heading_interpolation: HeadingInterpolation,
+}
+
+impl TrajectorySample {
+    fn interpolate(&self, other: &TrajectorySample, t: f32) -> TrajectorySample {
+        let mut pose = self.pose.lerp(&other.pose, t);
+
+        if self.heading_interpolation == HeadingInterpolation::ShortestPath {
+            pose.z = self.pose.z + wrap_angle(other.pose.z - self.pose.z) * t;
+        }
+
+        TrajectorySample {
+            time: self.time + (other.time - self.time) * t,
+            pose,
+            velocity: self.velocity.lerp(&other.velocity, t),
+            heading_interpolation: self.heading_interpolation,
+        }
+    }
+}
+
+/// A time parameterized path, sampled by linear interpolation between stored samples
+#[derive(Clone, Debug)]
+pub struct Trajectory {
+    samples: Vec<TrajectorySample>,
+}
+
+impl Trajectory {
+    pub fn new(samples: Vec<TrajectorySample>) -> anyhow::Result<Self> {
+        ensure!(
+            !samples.is_empty(),
+            "A trajectory needs at least one sample"
+        );
+        ensure!(
+            samples.windows(2).all(|pair| pair[0].time <= pair[1].time),
+            "Trajectory samples must be in time order"
+        );
+
+        Ok(Self { samples })
+    }
+
+    pub fn samples(&self) -> &[TrajectorySample] {
+        &self.samples
+    }
+
+    /// Seconds
+    pub fn total_time(&self) -> f32 {
+        self.samples[self.samples.len() - 1].time - self.samples[0].time
+    }
+
+    /// The interpolated state `time` seconds after the start, clamped to the ends
+    pub fn sample(&self, time: f32) -> TrajectorySample {
+        let time = self.samples[0].time + time;
+        let next = self.samples.partition_point(|sample| sample.time <= time);
+
+        if next == 0 {
+            return self.samples[0];
+        }
+        if next == self.samples.len() {
+            return self.samples[next - 1];
+        }
+
+        let previous = &self.samples[next - 1];
+        let next = &self.samples[next];
+
+        previous.interpolate(next, (time - previous.time) / (next.time - previous.time))
+    }
+}
+
+impl Trajectory {
+    /// Stretches time wherever the implied module speeds exceed `max_speed` (m/s), keeping the
+    /// path itself unchanged. Each sample's velocity is scaled down until its fastest module is
+    /// at the limit, and each segment is slowed by the larger scale of its two ends.
+    pub fn limit_module_speeds(
+        &self,
+        kinematics: &SwerveKinematics,
+        max_speed: f32,
+    ) -> anyhow::Result<Trajectory> {
+        ensure!(
+            max_speed > 0.0 && max_speed.is_finite(),
+            "Max module speed must be positive, got {max_speed}"
+        );
+
+        let scales = self
+            .samples
+            .iter()
+            .map(|sample| {
+                let translation = Rotation2::new(-sample.pose.z) * sample.velocity.xy();
+
+                let fastest = kinematics
+                    .inverse(translation.fixed_resize(sample.velocity.z))
+                    .into_iter()
+                    .map(|state| state.drive.abs())
+                    .fold(0.0, f32::max);
+
+                (fastest / max_speed).max(1.0)
+            })
+            .collect::<Vec<_>>();
+
+        let mut time = self.samples[0].time;
+        let mut samples = Vec::with_capacity(self.samples.len());
+
+        for (i, sample) in self.samples.iter().enumerate() {
+            if i > 0 {
+                let dt = sample.time - self.samples[i - 1].time;
+                time += dt * scales[i].max(scales[i - 1]);
+            }
+
+            samples.push(TrajectorySample {
+                time,
+                velocity: sample.velocity / scales[i],
+                ..*sample
+            });
+        }
+
+        Ok(Trajectory { samples })
+    }
+}
+
+/// When a trajectory event fires
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventTrigger {
+    /// Seconds since the start of the trajectory
+    Time(f32),
+    /// Fraction of the trajectory's total time, from 0 to 1
+    Fraction(f32),
+}
+
+impl EventTrigger {
+    /// Seconds since the start of `trajectory`
+    pub fn time(&self, trajectory: &Trajectory) -> f32 {
+        match *self {
+            EventTrigger::Time(time) => time,
+            EventTrigger::Fraction(fraction) => fraction * trajectory.total_time(),
+        }
+    }
+}
+
+/// A callback fired once when the trajectory follower passes its trigger
+pub struct TrajectoryEvent<'a> {
+    pub trigger: EventTrigger,
+    pub callback: Box<dyn FnMut() + 'a>,
+}
+
+impl<'a> TrajectoryEvent<'a> {
+    pub fn new(trigger: EventTrigger, callback: impl FnMut() + 'a) -> Self {
+        Self {
+            trigger,
+            callback: Box::new(callback),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+mod json {
+    use nalgebra::Vector3;
+    use serde::Deserialize;
+
+    use super::{HeadingInterpolation, Trajectory, TrajectorySample};
+
+    #[derive(Deserialize)]
+    struct State {
+        time: f32,
+        velocity: f32,
+        #[serde(default)]
+        curvature: f32,
+        pose: Pose,
+    }
+
+    #[derive(Deserialize)]
+    struct Pose {
+        translation: Translation,
+        rotation: Rotation,
+    }
+
+    #[derive(Deserialize)]
+    struct Translation {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Deserialize)]
+    struct Rotation {
+        radians: f32,
+    }
+
+    impl Trajectory {
+        /// Parses a trajectory in the WPILib JSON format exported by path planning tools: an
+        /// array of states with `time`, `velocity`, `curvature` and a `pose` of `translation`
+        /// meters and `rotation` radians. The speed is along the pose's rotation, and the
+        /// rotation rate is speed times curvature. Other fields are ignored.
+        pub fn from_wpilib_json(json: &str) -> anyhow::Result<Self> {
+            let states: Vec<State> = serde_json::from_str(json)?;
+
+            let samples = states
+                .into_iter()
+                .map(|state| {
+                    let heading = state.pose.rotation.radians;
+
+                    TrajectorySample {
+                        time: state.time,
+                        pose: Vector3::new(
+                            state.pose.translation.x,
+                            state.pose.translation.y,
+                            heading,
+                        ),
+                        velocity: Vector3::new(
+                            state.velocity * heading.cos(),
+                            state.velocity * heading.sin(),
+                            state.velocity * state.curvature,
+                        ),
+                        heading_interpolation: HeadingInterpolation::ShortestPath,
+                    }
+                })
+                .collect();
+
+            Self::new(samples)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::kinematics::module_positions_from_dimensions;
+
+    use super::*;
+
+    fn sample(time: f32, x: f32, velocity: Vector3<f32>) -> TrajectorySample {
+        TrajectorySample {
+            time,
+            pose: Vector3::new(x, 0.0, 0.0),
+            velocity,
+            heading_interpolation: HeadingInterpolation::ShortestPath,
+        }
+    }
+
+    #[test]
+    fn limit_module_speeds_stretches_fast_segments() {
+        let kinematics = SwerveKinematics::new(module_positions_from_dimensions(0.6, 0.6));
+        let fast = Vector3::new(2.0, 0.0, 0.0);
+        let slow = Vector3::new(0.5, 0.0, 0.0);
+
+        let trajectory = Trajectory::new(vec![
+            sample(0.0, 0.0, fast),
+            sample(1.0, 2.0, fast),
+            sample(2.0, 2.5, slow),
+            sample(3.0, 3.0, slow),
+        ])
+        .unwrap();
+        let limited = trajectory.limit_module_speeds(&kinematics, 1.0).unwrap();
+
+        let times = limited
+            .samples()
+            .iter()
+            .map(|sample| sample.time)
+            .collect::<Vec<_>>();
+        let speeds = limited
+            .samples()
+            .iter()
+            .map(|sample| sample.velocity.x)
+            .collect::<Vec<_>>();
+
+        // Each segment slows by the larger scale of its two ends
+        for (actual, expected) in times.iter().zip([0.0, 2.0, 4.0, 5.0]) {
+            assert!((actual - expected).abs() < 1e-5, "{times:?}");
+        }
+        for (actual, expected) in speeds.iter().zip([1.0, 1.0, 0.5, 0.5]) {
+            assert!((actual - expected).abs() < 1e-5, "{speeds:?}");
+        }
+
+        // The path itself is unchanged
+        for (limited, original) in limited.samples().iter().zip(trajectory.samples()) {
+            assert_eq!(limited.pose, original.pose);
+        }
+
+        for max_speed in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(trajectory
+                .limit_module_speeds(&kinematics, max_speed)
+                .is_err());
+        }
+    }
+
+    fn heading_sample(
+        time: f32,
+        heading: f32,
+        interpolation: HeadingInterpolation,
+    ) -> TrajectorySample {
+        TrajectorySample {
+            time,
+            pose: Vector3::new(0.0, 0.0, heading),
+            velocity: Vector3::zeros(),
+            heading_interpolation: interpolation,
+        }
+    }
+
+    fn midpoint_heading(from: f32, to: f32, interpolation: HeadingInterpolation) -> f32 {
+        Trajectory::new(vec![
+            heading_sample(0.0, from, interpolation),
+            heading_sample(1.0, to, interpolation),
+        ])
+        .unwrap()
+        .sample(0.5)
+        .pose
+        .z
+    }
+
+    #[test]
+    fn heading_interpolation() {
+        use std::f32::consts::{FRAC_PI_4, PI};
+        use HeadingInterpolation::*;
+
+        // Across the wrap at PI, the short way rather than back through zero
+        assert!((midpoint_heading(3.0, -3.0, ShortestPath) - PI).abs() < 1e-5);
+        assert!(midpoint_heading(3.0, -3.0, Linear).abs() < 1e-5);
+
+        // A full turn is nothing the short way, and half a turn literally
+        assert!(midpoint_heading(0.0, 2.0 * PI, ShortestPath).abs() < 1e-5);
+        assert!((midpoint_heading(0.0, 2.0 * PI, Linear) - PI).abs() < 1e-5);
+
+        // Below half a turn both agree, including clockwise
+        assert!((midpoint_heading(0.0, -PI / 2.0, Linear) + FRAC_PI_4).abs() < 1e-5);
+        assert!((midpoint_heading(0.0, -PI / 2.0, ShortestPath) + FRAC_PI_4).abs() < 1e-5);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn parses_wpilib_json() {
+        use std::f32::consts::FRAC_PI_2;
+
+        let json = r#"[
+            {
+                "time": 0.0,
+                "velocity": 0.0,
+                "acceleration": 1.0,
+                "pose": {"translation": {"x": 0.0, "y": 0.0}, "rotation": {"radians": 0.0}}
+            },
+            {
+                "time": 1.5,
+                "velocity": 2.0,
+                "acceleration": 0.0,
+                "curvature": 0.5,
+                "pose": {"translation": {"x": 1.0, "y": 2.0}, "rotation": {"radians": 1.5707964}}
+            }
+        ]"#;
+
+        let trajectory = Trajectory::from_wpilib_json(json).unwrap();
+        assert_eq!(trajectory.total_time(), 1.5);
+
+        let end = trajectory.samples()[1];
+        assert_eq!(end.pose, Vector3::new(1.0, 2.0, FRAC_PI_2));
+        assert!(end.velocity.x.abs() < 1e-5);
+        assert!((end.velocity.y - 2.0).abs() < 1e-5);
+        assert!((end.velocity.z - 1.0).abs() < 1e-5);
+
+        // Missing curvature means driving straight
+        assert_eq!(trajectory.samples()[0].velocity, Vector3::zeros());
+
+        assert!(Trajectory::from_wpilib_json("[{\"time\": 0.0}]").is_err());
+    }
+}
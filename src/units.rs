@@ -0,0 +1,58 @@
+use std::ops::Neg;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Radians(pub f32);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Degrees(pub f32);
+
+impl From<Degrees> for Radians {
+    fn from(value: Degrees) -> Self {
+        Radians(value.0.to_radians())
+    }
+}
+
+impl From<Radians> for Degrees {
+    fn from(value: Radians) -> Self {
+        Degrees(value.0.to_degrees())
+    }
+}
+
+impl Neg for Radians {
+    type Output = Radians;
+
+    fn neg(self) -> Self::Output {
+        Radians(-self.0)
+    }
+}
+
+impl Neg for Degrees {
+    type Output = Degrees;
+
+    fn neg(self) -> Self::Output {
+        Degrees(-self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn conversions_round_trip() {
+        assert!((Radians::from(Degrees(180.0)).0 - PI).abs() < 1e-6);
+        assert!((Degrees::from(Radians(PI)).0 - 180.0).abs() < 1e-4);
+
+        let degrees = Degrees(-37.5);
+        assert!((Degrees::from(Radians::from(degrees)).0 - degrees.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn negation_flips_the_sign() {
+        assert_eq!(-Radians(1.5), Radians(-1.5));
+        assert_eq!(-Degrees(90.0), Degrees(-90.0));
+        assert_eq!(Radians::from(-Degrees(90.0)), -Radians::from(Degrees(90.0)));
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    f32::consts::PI,
+    time::{Duration, Instant},
+};
+
+use anyhow::ensure;
+use nalgebra::Vector2;
+use robotrs::yield_now;
+
+/// Wraps an angle in radians into `[-PI, PI)`, for use on differences between angles
+pub fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// Turns a wrapped angle into a continuous one by accumulating wrapped differences, so crossing
+/// the seam never shows up as a full turn
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AngleUnwrapper {
+    last: Option<f32>,
+    continuous: f32,
+}
+
+impl AngleUnwrapper {
+    pub fn update(&mut self, angle: f32) -> f32 {
+        self.continuous = match self.last {
+            Some(last) => self.continuous + wrap_angle(angle - last),
+            None => angle,
+        };
+        self.last = Some(angle);
+
+        self.continuous
+    }
+}
+
+/// Runs a closure when dropped, for cleanup that has to happen when a future is cancelled
+pub struct OnDrop<F: FnOnce()>(Option<F>);
+
+impl<F: FnOnce()> OnDrop<F> {
+    pub fn new(f: F) -> Self {
+        Self(Some(f))
+    }
+}
+
+impl<F: FnOnce()> Drop for OnDrop<F> {
+    fn drop(&mut self) {
+        if let Some(f) = self.0.take() {
+            f();
+        }
+    }
+}
+
+/// Resolves after `duration` without blocking other tasks. There is no timer behind this: it
+/// yields back to the scheduler on every pass until the time is up, so it spaces work out but
+/// the task is still polled, and the CPU stays busy, while it waits.
+pub async fn delay(duration: Duration) {
+    let start = Instant::now();
+
+    while start.elapsed() < duration {
+        yield_now().await;
+    }
+}
+
+/// Zeroes vectors shorter than `radius` and rescales the rest so the output still spans the
+/// full `[0, 1]` magnitude range. Unlike a per-axis deadzone this is the same in every direction.
+pub fn radial_deadband(input: Vector2<f32>, radius: f32) -> Vector2<f32> {
+    let magnitude = input.norm();
+
+    if magnitude <= radius {
+        return Vector2::zeros();
+    }
+
+    let scaled = ((magnitude - radius) / (1.0 - radius)).min(1.0);
+
+    input * (scaled / magnitude)
+}
+
+/// A piecewise linear stick response: a gentler slope from center out to `breakpoint` for fine
+/// control, then a straight line up to full output at full stick. It maps 0 to 0 and 1 to 1, is
+/// continuous at the breakpoint and is symmetric for negative inputs. Apply it after a deadband,
+/// which already rescales to the full `[0, 1]` range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResponseCurve {
+    breakpoint: f32,
+    slope: f32,
+}
+
+impl ResponseCurve {
+    /// Output equals input
+    pub const LINEAR: ResponseCurve = ResponseCurve {
+        breakpoint: 0.0,
+        slope: 1.0,
+    };
+
+    /// `slope` is the output per unit of input below `breakpoint`, which is a fraction of full
+    /// stick in `[0, 1)`
+    pub fn new(breakpoint: f32, slope: f32) -> anyhow::Result<Self> {
+        ensure!(
+            (0.0..1.0).contains(&breakpoint),
+            "Response curve breakpoint must be in [0, 1), got {breakpoint}"
+        );
+        ensure!(
+            slope >= 0.0 && slope * breakpoint <= 1.0,
+            "Response curve slope must be non-negative and reach at most full output by the \
+             breakpoint, got {slope}"
+        );
+
+        Ok(Self { breakpoint, slope })
+    }
+
+    pub fn apply(&self, input: f32) -> f32 {
+        let magnitude = input.abs().min(1.0);
+        let knee = self.slope * self.breakpoint;
+
+        let output = if magnitude <= self.breakpoint {
+            self.slope * magnitude
+        } else {
+            knee + (magnitude - self.breakpoint) * (1.0 - knee) / (1.0 - self.breakpoint)
+        };
+
+        output.copysign(input)
+    }
+
+    /// Shapes the magnitude of a stick vector, keeping its direction
+    pub fn apply_vector(&self, input: Vector2<f32>) -> Vector2<f32> {
+        let magnitude = input.norm();
+
+        if magnitude == 0.0 {
+            return input;
+        }
+
+        input * (self.apply(magnitude) / magnitude)
+    }
+}
+
+impl Default for ResponseCurve {
+    fn default() -> Self {
+        Self::LINEAR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::pin,
+        task::{Context, Poll},
+    };
+
+    use futures::{executor::block_on, task::noop_waker_ref, Future};
+
+    use super::*;
+
+    #[test]
+    fn delay_waits_out_the_duration() {
+        let mut context = Context::from_waker(noop_waker_ref());
+        assert_eq!(
+            pin!(delay(Duration::ZERO)).poll(&mut context),
+            Poll::Ready(())
+        );
+
+        let duration = Duration::from_millis(20);
+        let start = Instant::now();
+        block_on(delay(duration));
+        assert!(start.elapsed() >= duration);
+    }
+
+    #[test]
+    fn response_curve_is_continuous_and_spans_the_range() {
+        let curve = ResponseCurve::new(0.5, 0.4).unwrap();
+
+        assert_eq!(curve.apply(0.0), 0.0);
+        assert!((curve.apply(1.0) - 1.0).abs() < 1e-6);
+        assert!((curve.apply(-1.0) + 1.0).abs() < 1e-6);
+
+        // Both pieces meet at the breakpoint
+        let knee = curve.apply(0.5);
+        assert!((knee - 0.2).abs() < 1e-6);
+        assert!((curve.apply(0.5 - 1e-4) - knee).abs() < 1e-3);
+        assert!((curve.apply(0.5 + 1e-4) - knee).abs() < 1e-3);
+
+        assert!((curve.apply(-0.25) + 0.1).abs() < 1e-6);
+
+        let shaped = curve.apply_vector(Vector2::new(0.3, 0.4));
+        assert!((shaped.norm() - 0.2).abs() < 1e-6);
+        assert!((shaped.normalize() - Vector2::new(0.6, 0.8)).norm() < 1e-6);
+
+        assert!(ResponseCurve::new(1.0, 1.0).is_err());
+        assert!(ResponseCurve::new(0.5, 3.0).is_err());
+        assert!(ResponseCurve::new(0.5, -1.0).is_err());
+    }
+}